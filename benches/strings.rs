@@ -0,0 +1,43 @@
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use rslox::interpreter::Backend;
+use rslox::lox::Lox;
+use std::io;
+
+// String-heavy scripts, each run on both backends
+const SCRIPTS: [(&str, &str); 3] = [
+    (
+        "string_equality",
+        include_str!("scripts/string_equality.lox"),
+    ),
+    (
+        "string_concatenation",
+        include_str!("scripts/string_concatenation.lox"),
+    ),
+    (
+        "string_variables",
+        include_str!("scripts/string_variables.lox"),
+    ),
+];
+
+fn strings(c: &mut Criterion) {
+    for (name, source) in SCRIPTS {
+        for (backend_name, backend) in [("tree-walker", Backend::TreeWalker), ("vm", Backend::Vm)] {
+            c.bench_function(&format!("{name}/{backend_name}"), |b| {
+                b.iter(|| {
+                    let mut lox = Lox::with_output(Box::new(io::sink()), Box::new(io::sink()));
+                    lox.set_backend(backend);
+                    lox.run(source).unwrap();
+                })
+            });
+        }
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = strings
+}
+criterion_main!(benches);
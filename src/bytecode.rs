@@ -0,0 +1,672 @@
+use crate::chunk::Chunk;
+use crate::chunk::Constant;
+use crate::chunk::Function;
+use crate::chunk::OpCode;
+use crate::interner;
+use crate::literal::Literal;
+use crate::result::Error;
+use crate::result::Result;
+use crate::token::Span;
+use crate::token::Token;
+use crate::token_type::TokenType;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::result;
+
+// On-disk form of a compiled script. Integers are stored most significant byte
+// first, like the operands in the code.
+//   file     -> magic version function
+//   function -> name arity upvalue_count code constants lines
+//   name     -> u8 (0: none, 1: followed by a string)
+//   code     -> u32 length, then the bytes
+//   constant -> u8 tag (0: f64 number, 1: string, 2: function)
+//   line     -> u32 offset, u32 line, u32 start, u32 end, u32 column, string lexeme
+//   string   -> u32 length, then UTF-8 bytes
+const MAGIC: &[u8; 4] = b"LOXB";
+// Changed whenever the layout or the instruction set changes
+const VERSION: u16 = 1;
+
+const NUMBER: u8 = 0;
+const STRING: u8 = 1;
+const FUNCTION: u8 = 2;
+
+// Encode a compiled script with its header.
+pub fn write(function: &Function) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_be_bytes());
+    write_function(&mut bytes, function);
+    bytes
+}
+
+// Decode a compiled script, checking its header, its structure and its code.
+pub fn read(bytes: &[u8]) -> Result<Rc<Function>> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(Error::Bytecode("Not a compiled Lox file.".to_string()));
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(Error::Bytecode(format!(
+            "Unsupported version {}, expected {}.",
+            version, VERSION
+        )));
+    }
+    let function = reader.function()?;
+    if reader.offset != bytes.len() {
+        return Err(Error::Bytecode(
+            "Unexpected data after the script.".to_string(),
+        ));
+    }
+    Ok(Rc::new(function))
+}
+
+fn write_function(bytes: &mut Vec<u8>, function: &Function) {
+    match &function.name {
+        Some(name) => {
+            bytes.push(1);
+            write_string(bytes, name);
+        }
+        None => bytes.push(0),
+    }
+    write_u32(bytes, function.arity);
+    write_u32(bytes, function.upvalue_count);
+
+    let chunk = &function.chunk;
+    write_u32(bytes, chunk.code.len());
+    bytes.extend(&chunk.code);
+    write_u32(bytes, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(n) => {
+                bytes.push(NUMBER);
+                bytes.extend(n.to_bits().to_be_bytes());
+            }
+            Constant::String(s) => {
+                bytes.push(STRING);
+                write_string(bytes, s);
+            }
+            Constant::Function(function) => {
+                bytes.push(FUNCTION);
+                write_function(bytes, function);
+            }
+        }
+    }
+    write_u32(bytes, chunk.lines.len());
+    for (offset, token) in &chunk.lines {
+        write_u32(bytes, *offset);
+        write_u32(bytes, token.line);
+        write_u32(bytes, token.span.start);
+        write_u32(bytes, token.span.end);
+        write_u32(bytes, token.span.column as usize);
+        write_string(bytes, &token.lexeme);
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, n: usize) {
+    bytes.extend((n as u32).to_be_bytes());
+}
+
+fn write_string(bytes: &mut Vec<u8>, s: &str) {
+    write_u32(bytes, s.len());
+    bytes.extend(s.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn function(&mut self) -> Result<Function> {
+        let name = match self.u8()? {
+            0 => None,
+            1 => Some(self.string()?),
+            flag => return Err(self.error(&format!("Invalid name flag {}.", flag))),
+        };
+        let arity = self.u32()?;
+        let upvalue_count = self.u32()?;
+
+        let mut chunk = Chunk::default();
+        let length = self.u32()?;
+        chunk.code = self.bytes(length)?.to_vec();
+        let count = self.u32()?;
+        for _ in 0..count {
+            let constant = match self.u8()? {
+                NUMBER => Constant::Number(f64::from_bits(self.u64()?)),
+                STRING => Constant::String(interner::intern(&self.string()?)),
+                FUNCTION => Constant::Function(Rc::new(self.function()?)),
+                tag => return Err(self.error(&format!("Invalid constant tag {}.", tag))),
+            };
+            chunk.constants.push(constant);
+        }
+        let count = self.u32()?;
+        for _ in 0..count {
+            let offset = self.u32()?;
+            let line = self.u32()?;
+            let span = Span::new(self.u32()?, self.u32()?, self.u32()? as u32);
+            let lexeme = self.string()?;
+            // Only what errors report is kept, so the token type is made up.
+            let token = Token::new(TokenType::Identifier, &lexeme, Literal::Nil, line);
+            chunk.lines.push((offset, token.with_span(span)));
+        }
+        // Every byte of code must have a line to report errors at.
+        let starts_at_zero = chunk.lines.first().is_some_and(|(o, _)| *o == 0);
+        let sorted = chunk.lines.windows(2).all(|w| w[0].0 < w[1].0);
+        let valid = chunk.code.is_empty() || (starts_at_zero && sorted);
+        if !valid {
+            return Err(self.error("Invalid line table."));
+        }
+
+        let function = Function {
+            name,
+            arity,
+            upvalue_count,
+            chunk,
+        };
+        verify(&function)?;
+        Ok(function)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&[u8]> {
+        let end = self.offset.saturating_add(length);
+        let Some(bytes) = self.bytes.get(self.offset..end) else {
+            return Err(Error::Bytecode("Unexpected end of file.".to_string()));
+        };
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<usize> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut n = [0; 8];
+        n.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(n))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.u32()?;
+        let offset = self.offset;
+        let bytes = self.bytes(length)?.to_vec();
+        String::from_utf8(bytes)
+            .map_err(|_| Error::Bytecode(format!("Invalid UTF-8 string at byte {}.", offset)))
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Bytecode(format!("{} (byte {})", message, self.offset))
+    }
+}
+
+// Instruction decoded for verification
+struct Instruction {
+    op: OpCode,
+    // First operand, or zero
+    operand: usize,
+    // Offset the instruction may jump to
+    target: Option<usize>,
+    // Stack slots captured by a closure
+    locals: Vec<usize>,
+    // Offset of the next instruction
+    next: usize,
+}
+
+// What the virtual machine holds for a call of the function at an instruction
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct State {
+    // Slots above the base of the frame, the callee included
+    stack: usize,
+    // Handlers pushed and not popped yet
+    handlers: usize,
+    // Errors waiting for a `finally` clause to end
+    pending: usize,
+}
+
+// Check that the code of a function can't break the virtual machine, which
+// trusts it: every operand must be in range, and every path must keep the
+// stack and the handlers balanced and end with a return or a throw.
+// The compiler guarantees this for the code it writes.
+fn verify(function: &Function) -> Result<()> {
+    let error = |offset: usize, message: &str| {
+        Error::Bytecode(format!("{} (code offset {})", message, offset))
+    };
+
+    let code = &function.chunk.code;
+    let mut instructions = BTreeMap::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let instruction = decode(function, offset).map_err(|m| error(offset, &m))?;
+        let next = instruction.next;
+        instructions.insert(offset, instruction);
+        offset = next;
+    }
+    for (offset, instruction) in &instructions {
+        if let Some(target) = instruction.target {
+            if !instructions.contains_key(&target) {
+                return Err(error(*offset, &format!("Invalid jump target {}.", target)));
+            }
+        }
+    }
+
+    // Follow every path from the start, the callee and the arguments on the stack.
+    let mut states = HashMap::new();
+    let start = State {
+        stack: function.arity + 1,
+        handlers: 0,
+        pending: 0,
+    };
+    let mut paths = vec![(0, start)];
+    while let Some((offset, state)) = paths.pop() {
+        let Some(instruction) = instructions.get(&offset) else {
+            return Err(error(offset, "Unexpected end of code."));
+        };
+        match states.get(&offset) {
+            Some(seen) if *seen == state => continue,
+            Some(_) => {
+                return Err(error(
+                    offset,
+                    "Paths reach this code with different stacks.",
+                ))
+            }
+            None => states.insert(offset, state),
+        };
+        let next = step(instruction, state).map_err(|m| error(offset, &m))?;
+        let falls_through = !matches!(
+            instruction.op,
+            OpCode::Jump | OpCode::Loop | OpCode::Return | OpCode::Throw | OpCode::Rethrow
+        );
+        if falls_through && instruction.next == code.len() {
+            return Err(error(offset, "Code ends without a return."));
+        }
+
+        let target = instruction.target.unwrap_or_default();
+        match instruction.op {
+            OpCode::Jump | OpCode::Loop => paths.push((target, next)),
+            OpCode::JumpIfFalse => paths.push((target, next)),
+            // Handlers restore the stack as it is now, catching pushes the error.
+            OpCode::PushCatch => paths.push((
+                target,
+                State {
+                    stack: state.stack + 1,
+                    ..state
+                },
+            )),
+            OpCode::PushFinally => paths.push((
+                target,
+                State {
+                    pending: state.pending + 1,
+                    ..state
+                },
+            )),
+            _ => (),
+        }
+        if falls_through {
+            paths.push((instruction.next, next));
+        }
+    }
+    Ok(())
+}
+
+// Instruction at `offset` with its operands, which must refer to existing
+// constants of the right kind and to existing upvalues.
+fn decode(function: &Function, offset: usize) -> result::Result<Instruction, String> {
+    let chunk = &function.chunk;
+    let byte = chunk.code[offset];
+    let op = OpCode::from_byte(byte).ok_or(format!("Unknown opcode {}.", byte))?;
+    let u8_at = |at: usize| match chunk.code.get(at) {
+        Some(byte) => Ok(*byte as usize),
+        None => Err("Unexpected end of code.".to_string()),
+    };
+    let u16_at = |at: usize| match chunk.code.get(at..at + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize),
+        None => Err("Unexpected end of code.".to_string()),
+    };
+    let constant = |index: usize| {
+        chunk
+            .constants
+            .get(index)
+            .ok_or(format!("Invalid constant {}.", index))
+    };
+    let string = |index: usize| match constant(index)? {
+        Constant::String(_) => Ok(()),
+        _ => Err(format!("Constant {} is not a string.", index)),
+    };
+    let upvalue = |index: usize| match index < function.upvalue_count {
+        true => Ok(()),
+        false => Err(format!("Invalid upvalue {}.", index)),
+    };
+
+    let mut instruction = Instruction {
+        op,
+        operand: 0,
+        target: None,
+        locals: Vec::new(),
+        next: offset + 1,
+    };
+    match op {
+        OpCode::Constant => {
+            instruction.operand = u16_at(offset + 1)?;
+            if let Constant::Function(_) = constant(instruction.operand)? {
+                return Err(format!("Constant {} is a function.", instruction.operand));
+            }
+            instruction.next = offset + 3;
+        }
+        OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Import => {
+            string(u16_at(offset + 1)?)?;
+            instruction.next = offset + 3;
+        }
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::List | OpCode::Map | OpCode::Interpolate => {
+            instruction.operand = u16_at(offset + 1)?;
+            instruction.next = offset + 3;
+        }
+        OpCode::GetUpvalue | OpCode::SetUpvalue => {
+            upvalue(u16_at(offset + 1)?)?;
+            instruction.next = offset + 3;
+        }
+        OpCode::Call => {
+            instruction.operand = u8_at(offset + 1)?;
+            instruction.next = offset + 2;
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::PushCatch | OpCode::PushFinally => {
+            instruction.next = offset + 3;
+            instruction.target = Some(instruction.next + u16_at(offset + 1)?);
+        }
+        OpCode::Loop => {
+            instruction.next = offset + 3;
+            let target = instruction.next.checked_sub(u16_at(offset + 1)?);
+            let target = target.ok_or("Loop jumps before the start of the code.")?;
+            instruction.target = Some(target);
+        }
+        OpCode::Class | OpCode::Subclass => {
+            string(u16_at(offset + 1)?)?;
+            instruction.operand = u16_at(offset + 3)?;
+            instruction.next = offset + 5;
+        }
+        OpCode::Closure => {
+            let index = u16_at(offset + 1)?;
+            let Constant::Function(closed) = constant(index)? else {
+                return Err(format!("Constant {} is not a function.", index));
+            };
+            let mut next = offset + 3;
+            for _ in 0..closed.upvalue_count {
+                let index = u16_at(next + 1)?;
+                match u8_at(next)? {
+                    1 => instruction.locals.push(index),
+                    0 => upvalue(index)?,
+                    flag => return Err(format!("Invalid upvalue flag {}.", flag)),
+                }
+                next += 3;
+            }
+            instruction.next = next;
+        }
+        _ => (),
+    }
+    Ok(instruction)
+}
+
+// State after an instruction, which must find the values it uses on the stack.
+fn step(instruction: &Instruction, state: State) -> result::Result<State, String> {
+    let n = instruction.operand;
+    let slot = |slot: usize| match slot < state.stack {
+        true => Ok(()),
+        false => Err(format!("Invalid local slot {}.", slot)),
+    };
+    let (pops, pushes) = match instruction.op {
+        OpCode::Constant
+        | OpCode::Nil
+        | OpCode::True
+        | OpCode::False
+        | OpCode::GetGlobal
+        | OpCode::GetUpvalue
+        | OpCode::Import => (0, 1),
+        OpCode::GetLocal => {
+            slot(n)?;
+            (0, 1)
+        }
+        OpCode::SetLocal => {
+            slot(n)?;
+            (1, 1)
+        }
+        // A local function captures the slot the closure is about to take.
+        OpCode::Closure => match instruction.locals.iter().find(|l| **l > state.stack) {
+            Some(local) => return Err(format!("Invalid local slot {}.", local)),
+            None => (0, 1),
+        },
+        OpCode::Pop
+        | OpCode::DefineGlobal
+        | OpCode::Print
+        | OpCode::CloseUpvalue
+        | OpCode::Return
+        | OpCode::Throw => (1, 0),
+        OpCode::SetGlobal
+        | OpCode::SetUpvalue
+        | OpCode::GetProperty
+        | OpCode::Not
+        | OpCode::Negate
+        | OpCode::JumpIfFalse
+        | OpCode::Superclass => (1, 1),
+        OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::GetIndex
+        | OpCode::Equal
+        | OpCode::NotEqual
+        | OpCode::Greater
+        | OpCode::GreaterEqual
+        | OpCode::Less
+        | OpCode::LessEqual
+        | OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide => (2, 1),
+        OpCode::SetIndex => (3, 1),
+        OpCode::Call => (n + 1, 1),
+        OpCode::List | OpCode::Interpolate => (n, 1),
+        OpCode::Map => (2 * n, 1),
+        OpCode::Class => (n, 1),
+        // The superclass stays below the class.
+        OpCode::Subclass => (n + 1, 2),
+        OpCode::Jump
+        | OpCode::Loop
+        | OpCode::PushCatch
+        | OpCode::PushFinally
+        | OpCode::PopHandler
+        | OpCode::Rethrow
+        | OpCode::DropError => (0, 0),
+    };
+    if state.stack < pops {
+        return Err("Stack underflow.".to_string());
+    }
+    let mut next = State {
+        stack: state.stack - pops + pushes,
+        ..state
+    };
+
+    match instruction.op {
+        OpCode::PushCatch | OpCode::PushFinally => next.handlers += 1,
+        OpCode::PopHandler if state.handlers == 0 => return Err("No handler to pop.".to_string()),
+        OpCode::PopHandler => next.handlers -= 1,
+        OpCode::Rethrow | OpCode::DropError if state.pending == 0 => {
+            return Err("No pending error.".to_string())
+        }
+        OpCode::DropError => next.pending -= 1,
+        // Handlers outliving their frame would jump back into it.
+        OpCode::Return if state.handlers > 0 || state.pending > 0 => {
+            return Err("Return with an active handler.".to_string())
+        }
+        _ => (),
+    }
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::disassembler;
+    use crate::lox;
+
+    fn compile(source: &str) -> Rc<Function> {
+        let stmts = lox::parse(source).unwrap();
+        Compiler::new().run(&stmts).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let function =
+            compile("fun add(a, b) {\n  return a + b;\n}\nprint add(1.5, 2);\nprint \"done\";");
+        let loaded = read(&write(&function)).unwrap();
+        assert_eq!(
+            disassembler::disassemble(&loaded, "script"),
+            disassembler::disassemble(&function, "script")
+        );
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        let bytes = write(&compile("print 1;"));
+        let message = |bytes: &[u8]| match read(bytes) {
+            Err(Error::Bytecode(message)) => message,
+            result => panic!("Expected a bytecode error, got {:?}", result),
+        };
+
+        assert_eq!(message(b"print 1;"), "Not a compiled Lox file.");
+        for length in 0..bytes.len() {
+            let message = message(&bytes[..length]);
+            assert!(
+                message == "Unexpected end of file." || message == "Not a compiled Lox file.",
+                "{}",
+                message
+            );
+        }
+
+        let mut other_version = bytes.clone();
+        other_version[5] += 1;
+        assert_eq!(
+            message(&other_version),
+            "Unsupported version 2, expected 1."
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(message(&trailing), "Unexpected data after the script.");
+
+        // Code the virtual machine would fail on
+        let number = || vec![Constant::Number(1.0)];
+        let cases = [
+            (
+                vec![OpCode::Constant as u8, 0, 5],
+                vec![],
+                "Invalid constant 5. (code offset 0)",
+            ),
+            (
+                vec![OpCode::Constant as u8],
+                vec![],
+                "Unexpected end of code. (code offset 0)",
+            ),
+            (vec![200], vec![], "Unknown opcode 200. (code offset 0)"),
+            (
+                vec![OpCode::Pop as u8, OpCode::Return as u8],
+                vec![],
+                "Stack underflow. (code offset 1)",
+            ),
+            (
+                vec![OpCode::Nil as u8],
+                vec![],
+                "Code ends without a return. (code offset 0)",
+            ),
+            (
+                vec![OpCode::Jump as u8, 0, 10],
+                vec![],
+                "Invalid jump target 13. (code offset 0)",
+            ),
+            (
+                vec![OpCode::Loop as u8, 0, 10],
+                vec![],
+                "Loop jumps before the start of the code. (code offset 0)",
+            ),
+            (
+                vec![OpCode::GetGlobal as u8, 0, 0, OpCode::Return as u8],
+                number(),
+                "Constant 0 is not a string. (code offset 0)",
+            ),
+            (
+                vec![OpCode::Closure as u8, 0, 0, OpCode::Return as u8],
+                number(),
+                "Constant 0 is not a function. (code offset 0)",
+            ),
+            (
+                vec![OpCode::GetUpvalue as u8, 0, 0, OpCode::Return as u8],
+                vec![],
+                "Invalid upvalue 0. (code offset 0)",
+            ),
+            (
+                vec![OpCode::GetLocal as u8, 0, 1, OpCode::Return as u8],
+                vec![],
+                "Invalid local slot 1. (code offset 0)",
+            ),
+            (
+                vec![OpCode::Rethrow as u8],
+                vec![],
+                "No pending error. (code offset 0)",
+            ),
+            (
+                vec![
+                    OpCode::PushCatch as u8,
+                    0,
+                    1,
+                    OpCode::Return as u8,
+                    OpCode::Return as u8,
+                ],
+                vec![],
+                "Return with an active handler. (code offset 3)",
+            ),
+            (
+                vec![
+                    OpCode::Nil as u8,
+                    OpCode::JumpIfFalse as u8,
+                    0,
+                    1,
+                    OpCode::Nil as u8,
+                    OpCode::Return as u8,
+                ],
+                vec![],
+                "Paths reach this code with different stacks. (code offset 5)",
+            ),
+        ];
+        for (code, constants, expected) in cases {
+            assert_eq!(message(&script(code, constants)), expected);
+        }
+    }
+
+    // File of a script made of `code`, written without checking it.
+    fn script(code: Vec<u8>, constants: Vec<Constant>) -> Vec<u8> {
+        let token = Token::new(TokenType::Nil, "nil", Literal::Nil, 1);
+        let chunk = Chunk {
+            code,
+            constants,
+            lines: vec![(0, token)],
+        };
+        write(&Function {
+            chunk,
+            ..Function::default()
+        })
+    }
+}
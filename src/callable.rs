@@ -1,9 +1,46 @@
 use crate::interpreter::Interpreter;
 use crate::result::Result;
 use crate::value::Value;
+use std::fmt;
 
 pub trait Callable {
     // Number of arguments
-    fn arity(&self) -> usize;
+    fn arity(&self) -> Arity;
     fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value>;
 }
+
+// Number of arguments a callable accepts
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Arity {
+    Fixed(usize),
+    AtLeast(usize),
+    Any,
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Self::Fixed(n) => count == *n,
+            Self::AtLeast(n) => count >= *n,
+            Self::Any => true,
+        }
+    }
+}
+
+impl From<usize> for Arity {
+    fn from(value: usize) -> Self {
+        Self::Fixed(value)
+    }
+}
+
+// Counts with the noun agreeing, e.g. "1 argument" or "at least 2 arguments"
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let noun = |n: usize| if n == 1 { "argument" } else { "arguments" };
+        match self {
+            Self::Fixed(n) => write!(f, "{n} {}", noun(*n)),
+            Self::AtLeast(n) => write!(f, "at least {n} {}", noun(*n)),
+            Self::Any => write!(f, "any number of arguments"),
+        }
+    }
+}
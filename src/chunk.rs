@@ -0,0 +1,241 @@
+use crate::token::Token;
+use std::fmt;
+use std::rc::Rc;
+
+// Instructions of the virtual machine, one byte each.
+// Operands follow the opcode: argument counts take one byte, every other operand
+// takes two bytes, most significant first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant, // constant index
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,     // stack slot
+    SetLocal,     // stack slot
+    GetGlobal,    // name constant
+    DefineGlobal, // name constant
+    SetGlobal,    // name constant
+    GetUpvalue,   // upvalue index
+    SetUpvalue,   // upvalue index
+    GetProperty,  // name constant
+    SetProperty,  // name constant
+    GetSuper,     // name constant
+    GetIndex,
+    SetIndex,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,        // forward offset
+    JumpIfFalse, // forward offset
+    Loop,        // backward offset
+    Call,        // argument count
+    Closure,     // function constant, then a local flag and an index per upvalue
+    CloseUpvalue,
+    Return,
+    Class,       // name constant, method count
+    Subclass,    // name constant, method count
+    Superclass,  // checks the value on top of the stack is a class
+    List,        // element count
+    Map,         // entry count
+    Interpolate, // part count
+    Import,      // path constant
+    Throw,
+    PushCatch,   // handler offset
+    PushFinally, // handler offset
+    PopHandler,
+    Rethrow,
+    DropError,
+}
+
+impl OpCode {
+    // Every opcode, indexed by its byte
+    const ALL: [OpCode; 50] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::GetIndex,
+        OpCode::SetIndex,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Subclass,
+        OpCode::Superclass,
+        OpCode::List,
+        OpCode::Map,
+        OpCode::Interpolate,
+        OpCode::Import,
+        OpCode::Throw,
+        OpCode::PushCatch,
+        OpCode::PushFinally,
+        OpCode::PopHandler,
+        OpCode::Rethrow,
+        OpCode::DropError,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+// Value known at compile time, stored in the constant pool of a chunk
+#[derive(Clone, Debug)]
+pub enum Constant {
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write!(f, "{s}"),
+            Self::Function(function) => write!(f, "<fn {}>", function.name()),
+        }
+    }
+}
+
+// Function compiled to bytecode, shared by every closure created from it
+#[derive(Debug, Default)]
+pub struct Function {
+    // None for the script and for anonymous functions
+    pub name: Option<String>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+impl Function {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("anonymous")
+    }
+}
+
+// Bytecode of a function with its constant pool and line table
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    // Token each run of bytes was compiled from, with the offset the run starts at.
+    // Runtime errors are reported at these tokens.
+    pub lines: Vec<(usize, Token)>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, token: &Token) {
+        let same = self
+            .lines
+            .last()
+            .is_some_and(|(_, t)| t.line == token.line && t.span == token.span);
+        if !same {
+            self.lines.push((self.code.len(), token.clone()));
+        }
+        self.code.push(byte);
+    }
+
+    // Index of the constant, reusing an equal number or string already in the pool.
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        let existing = self.constants.iter().position(|c| match (c, &constant) {
+            (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+            (Constant::String(a), Constant::String(b)) => a == b,
+            _ => false,
+        });
+        existing.unwrap_or_else(|| {
+            self.constants.push(constant);
+            self.constants.len() - 1
+        })
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    // Token the byte at `offset` was compiled from.
+    pub fn token(&self, offset: usize) -> &Token {
+        let i = self.lines.partition_point(|(start, _)| *start <= offset);
+        &self.lines[i.saturating_sub(1)].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::literal::Literal;
+    use crate::token_type::TokenType;
+
+    #[test]
+    fn decode_every_opcode() {
+        for (i, op) in OpCode::ALL.iter().enumerate() {
+            assert_eq!(*op as usize, i);
+            assert_eq!(OpCode::from_byte(i as u8), Some(*op));
+        }
+        assert_eq!(OpCode::from_byte(OpCode::ALL.len() as u8), None);
+    }
+
+    #[test]
+    fn look_up_lines() {
+        let first = Token::new(TokenType::Print, "print", Literal::Nil, 1);
+        let second = Token::new(TokenType::Minus, "-", Literal::Nil, 3);
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Nil as u8, &first);
+        chunk.write(OpCode::Negate as u8, &second);
+        chunk.write(OpCode::Print as u8, &first);
+        chunk.write(OpCode::Return as u8, &first);
+        assert_eq!(chunk.lines.len(), 3);
+        let lines: Vec<usize> = (0..4).map(|offset| chunk.token(offset).line).collect();
+        assert_eq!(lines, vec![1, 3, 1, 1]);
+    }
+
+    #[test]
+    fn reuse_constants() {
+        let mut chunk = Chunk::default();
+        assert_eq!(chunk.add_constant(Constant::Number(1.0)), 0);
+        assert_eq!(chunk.add_constant(Constant::String(Rc::from("a"))), 1);
+        assert_eq!(chunk.add_constant(Constant::Number(1.0)), 0);
+        assert_eq!(chunk.add_constant(Constant::String(Rc::from("a"))), 1);
+        assert_eq!(chunk.constants.len(), 2);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::callable::Arity;
+use crate::callable::Callable;
+use crate::gc;
+use crate::gc::Trace;
+use crate::gc::Tracer;
+use crate::interner;
+use crate::interpreter::Interpreter;
+use crate::result::Error;
+use crate::result::Result;
+use crate::token::Token;
+use crate::value::Value;
+use crate::vm::BoundMethod;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+
+// User-defined class
+#[derive(Debug)]
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    // Functions of the tree-walker or closures of the VM, depending on the backend
+    methods: HashMap<String, Value>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: &str,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, Value>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            superclass,
+            methods,
+        }
+    }
+
+    // Look up a method on this class, then up the superclass chain.
+    pub fn find_method(&self, name: &str) -> Option<&Value> {
+        match self.methods.get(name) {
+            Some(method) => Some(method),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+
+    // Look up a method and bind `this` to the instance.
+    pub fn bind_method(&self, name: &str, instance: &Rc<LoxInstance>) -> Result<Option<Value>> {
+        match self.find_method(name) {
+            Some(Value::LoxFunction(method)) => {
+                Ok(Some(Value::LoxFunction(method.bind(Rc::clone(instance))?)))
+            }
+            Some(Value::Closure(method)) => Ok(Some(Value::BoundMethod(gc::alloc(
+                BoundMethod::new(Value::LoxInstance(Rc::clone(instance)), Rc::clone(method)),
+            )))),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Callable for Rc<LoxClass> {
+    fn arity(&self) -> Arity {
+        match self.find_method("init").and_then(Value::as_callable) {
+            Some(initializer) => initializer.arity(),
+            None => Arity::Fixed(0),
+        }
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+        let instance = gc::alloc(LoxInstance::new(Rc::clone(self)));
+        if let Some(initializer) = self.bind_method("init", &instance)? {
+            if let Some(initializer) = initializer.as_callable() {
+                initializer.call(interpreter, arguments)?;
+            }
+        }
+        Ok(Value::LoxInstance(instance))
+    }
+}
+
+impl fmt::Display for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+// Classes are only equal to themselves
+impl cmp::PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for LoxClass {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+// Runtime representation of an instance of a class
+#[derive(Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: RefCell<HashMap<Rc<str>, Value>>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Fields shadow methods with the same name.
+    pub fn get(instance: &Rc<Self>, name: &Token) -> Result<Value> {
+        match Self::property(instance, &name.lexeme)? {
+            Some(value) => Ok(value),
+            None => Err(Error::Runtime(
+                name.clone(),
+                format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+
+    // Field or bound method called `name`, if there is one.
+    pub fn property(instance: &Rc<Self>, name: &str) -> Result<Option<Value>> {
+        if let Some(value) = instance.fields.borrow().get(name) {
+            return Ok(Some(value.clone()));
+        }
+        instance.class.bind_method(name, instance)
+    }
+
+    pub fn set(&self, name: &str, value: Value) {
+        let mut fields = self.fields.borrow_mut();
+        match fields.get_mut(name) {
+            Some(field) => *field = value,
+            None => {
+                fields.insert(interner::intern(name), value);
+            }
+        }
+    }
+}
+
+impl fmt::Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+// Instances are only equal to themselves
+impl cmp::PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for LoxInstance {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Some(superclass) = &self.superclass {
+            tracer.mark(superclass);
+        }
+        self.methods.values().for_each(|m| tracer.mark_value(m));
+    }
+
+    // Methods can't change, so a class is only part of a cycle through something
+    // that can, like the environment it is declared in.
+    fn clear(&self) {}
+
+    fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.methods.capacity() * mem::size_of::<(String, Value)>()
+    }
+}
+
+impl Trace for LoxInstance {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.class);
+        if let Ok(fields) = self.fields.try_borrow() {
+            fields.values().for_each(|v| tracer.mark_value(v));
+        }
+    }
+
+    fn clear(&self) {
+        let fields = self.fields.try_borrow_mut().map(|mut f| mem::take(&mut *f));
+        drop(fields);
+    }
+
+    fn size(&self) -> usize {
+        let capacity = self.fields.try_borrow().map_or(0, |f| f.capacity());
+        mem::size_of::<Self>() + capacity * mem::size_of::<(Rc<str>, Value)>()
+    }
+}
@@ -0,0 +1,808 @@
+use crate::chunk::Chunk;
+use crate::chunk::Constant;
+use crate::chunk::Function;
+use crate::chunk::OpCode;
+use crate::expr::Expr;
+use crate::interner;
+use crate::literal::Literal;
+use crate::result::Error;
+use crate::result::Result;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use crate::token_type::TokenType;
+use crate::value::Value;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FunctionType {
+    Script,
+    Function,
+    Initializer,
+    Method,
+}
+
+// Local variable, living in the stack slot of its index
+#[derive(Debug)]
+struct Local {
+    name: String,
+    depth: usize,
+    // Whether a closure refers to it, so that it must outlive its scope
+    is_captured: bool,
+}
+
+// Variable of an enclosing function referred to by a closure
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Upvalue {
+    // Slot of the local if `is_local`, else index of the enclosing function's upvalue
+    index: u16,
+    is_local: bool,
+}
+
+// Statement that code jumping out of it must clean up after
+#[derive(Clone, Debug)]
+enum Exit {
+    // Loop, with the number of locals declared outside of it and the jumps
+    // to patch once its end is known
+    Loop {
+        locals: usize,
+        breaks: Vec<usize>,
+        continues: Vec<usize>,
+    },
+    // `try` block guarded by a `catch` clause
+    Catch,
+    // `try` block or `catch` clause guarded by a `finally` clause, which runs
+    // again on the way out
+    Finally(Box<Stmt>),
+    // `finally` clause run while an error waits to be raised again
+    Pending,
+}
+
+// Where a `break`, `continue` or `return` jumps to
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Loop,
+    Function,
+}
+
+// State of a function being compiled
+#[derive(Debug)]
+struct FunctionCompiler {
+    function: Function,
+    function_type: FunctionType,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+    exits: Vec<Exit>,
+}
+
+impl FunctionCompiler {
+    fn new(name: Option<&Token>, function_type: FunctionType) -> Self {
+        // The first slot holds the function being called, or the instance in methods.
+        let slot_zero = match function_type {
+            FunctionType::Initializer | FunctionType::Method => "this",
+            _ => "",
+        };
+        Self {
+            function: Function {
+                name: name.map(|n| n.lexeme.clone()),
+                ..Function::default()
+            },
+            function_type,
+            locals: vec![Local {
+                name: slot_zero.to_string(),
+                depth: 0,
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            exits: Vec::new(),
+        }
+    }
+}
+
+// Compiles resolved statements to bytecode for the virtual machine.
+// Static errors have already been reported by the resolver, so only the limits
+// of the bytecode format can make compilation fail.
+pub struct Compiler {
+    // Functions being compiled, the innermost last
+    functions: Vec<FunctionCompiler>,
+    // Token of the code being compiled, recorded in the line table
+    token: Token,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self {
+            functions: Vec::new(),
+            token: Token::new(TokenType::Eof, "", Literal::Nil, 0),
+        }
+    }
+
+    // Compile a script into a function returning the value of its last statement
+    // if it is an expression, or nil.
+    pub fn run(mut self, statements: &[Stmt]) -> Result<Rc<Function>> {
+        self.functions
+            .push(FunctionCompiler::new(None, FunctionType::Script));
+        match statements.split_last() {
+            Some((Stmt::Expression(expr), rest)) => {
+                for statement in rest {
+                    self.statement(statement)?;
+                }
+                self.expression(expr)?;
+            }
+            _ => {
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.emit(OpCode::Nil);
+            }
+        }
+        self.emit(OpCode::Return);
+        let compiler = self.functions.pop().expect("Missing script compiler.");
+        Ok(Rc::new(compiler.function))
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Block(stmts) => {
+                self.begin_scope();
+                for stmt in stmts {
+                    self.statement(stmt)?;
+                }
+                self.end_scope();
+            }
+            Stmt::Break(keyword) => {
+                self.token = keyword.clone();
+                self.jump_out(Target::Loop, false)?;
+            }
+            Stmt::Class(name, superclass, methods) => self.class(name, superclass, methods)?,
+            Stmt::Continue(keyword) => {
+                self.token = keyword.clone();
+                self.jump_out(Target::Loop, true)?;
+            }
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit(OpCode::Pop);
+            }
+            Stmt::Function(name, params, body) => {
+                self.declare_variable(name);
+                self.token = name.clone();
+                self.function(Some(name), params, body, FunctionType::Function)?;
+                self.define_variable(name)?;
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expression(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.statement(then_branch)?;
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit(OpCode::Pop);
+                if let Some(b) = else_branch {
+                    self.statement(b)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            Stmt::Import(_keyword, name, path) => {
+                self.declare_variable(name);
+                self.token = path.clone();
+                let Literal::String(relative) = &path.literal else {
+                    unreachable!()
+                };
+                let constant = self.make_constant(Constant::String(interner::intern(relative)))?;
+                self.emit_with(OpCode::Import, constant);
+                self.define_variable(name)?;
+            }
+            Stmt::Print(keyword, expr) => {
+                self.token = keyword.clone();
+                self.expression(expr)?;
+                self.emit(OpCode::Print);
+            }
+            Stmt::Return(keyword, value) => {
+                self.token = keyword.clone();
+                match value {
+                    Some(v) => self.expression(v)?,
+                    None => self.emit_implicit_value(),
+                }
+                self.jump_out(Target::Function, false)?;
+            }
+            Stmt::Throw(keyword, value) => {
+                self.expression(value)?;
+                self.token = keyword.clone();
+                self.emit(OpCode::Throw);
+            }
+            Stmt::Try(body, handler, finally) => self.try_statement(body, handler, finally)?,
+            Stmt::Var(name, initializer) => {
+                self.declare_variable(name);
+                // Literals have no token, so they are placed at the variable.
+                self.token = name.clone();
+                match initializer {
+                    Some(i) => self.expression(i)?,
+                    None => self.emit(OpCode::Nil),
+                }
+                self.define_variable(name)?;
+            }
+            Stmt::While(condition, body, increment) => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+
+                let locals = self.current().locals.len();
+                self.current().exits.push(Exit::Loop {
+                    locals,
+                    breaks: Vec::new(),
+                    continues: Vec::new(),
+                });
+                self.statement(body)?;
+                let Some(Exit::Loop {
+                    breaks, continues, ..
+                }) = self.current().exits.pop()
+                else {
+                    unreachable!()
+                };
+
+                for jump in continues {
+                    self.patch_jump(jump)?;
+                }
+                if let Some(i) = increment {
+                    self.expression(i)?;
+                    self.emit(OpCode::Pop);
+                }
+                self.emit_loop(loop_start)?;
+
+                self.patch_jump(exit_jump)?;
+                self.emit(OpCode::Pop);
+                for jump in breaks {
+                    self.patch_jump(jump)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn class(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) -> Result<()> {
+        // The name is bound before the methods are compiled so that they can refer to it.
+        self.token = name.clone();
+        self.declare_variable(name);
+        self.emit(OpCode::Nil);
+        self.define_variable(name)?;
+
+        // Methods of a subclass close over a local holding the superclass.
+        if let Some(s) = superclass {
+            self.expression(s)?;
+            if let Expr::Variable(token, _) = s {
+                self.token = token.clone();
+            }
+            self.emit(OpCode::Superclass);
+            self.begin_scope();
+            self.add_local("super");
+        }
+
+        for method in methods {
+            if let Stmt::Function(method_name, params, body) = method {
+                let function_type = match method_name.lexeme.as_str() {
+                    "init" => FunctionType::Initializer,
+                    _ => FunctionType::Method,
+                };
+                self.token = method_name.clone();
+                self.function(Some(method_name), params, body, function_type)?;
+            }
+        }
+
+        self.token = name.clone();
+        let constant = self.identifier_constant(name)?;
+        let op = match superclass {
+            Some(_) => OpCode::Subclass,
+            None => OpCode::Class,
+        };
+        self.emit_with(op, constant);
+        self.emit_u16(self.operand(methods.len(), "Too many methods in one class.")?);
+        self.set_variable(name)?;
+        self.emit(OpCode::Pop);
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    // try { body } catch (name) { handler } finally { finally }
+    //
+    // The body runs under a handler jumping to the `catch` clause, and both run
+    // under a handler jumping to a copy of the `finally` clause that raises the
+    // error again. On the way out, normally or by `break`, `continue` or `return`,
+    // the handlers are dropped and the `finally` clause runs inline.
+    fn try_statement(
+        &mut self,
+        body: &Stmt,
+        handler: &Option<(Token, Box<Stmt>)>,
+        finally: &Option<Box<Stmt>>,
+    ) -> Result<()> {
+        let finally_handler = match finally {
+            Some(f) => {
+                let jump = self.emit_jump(OpCode::PushFinally);
+                self.current().exits.push(Exit::Finally(f.clone()));
+                Some(jump)
+            }
+            None => None,
+        };
+        let catch_handler = match handler {
+            Some(_) => {
+                let jump = self.emit_jump(OpCode::PushCatch);
+                self.current().exits.push(Exit::Catch);
+                Some(jump)
+            }
+            None => None,
+        };
+
+        self.statement(body)?;
+
+        if let (Some(jump), Some((name, handler))) = (catch_handler, handler) {
+            self.current().exits.pop();
+            self.emit(OpCode::PopHandler);
+            let skip = self.emit_jump(OpCode::Jump);
+
+            // The virtual machine pushes the caught value, which becomes the variable.
+            self.patch_jump(jump)?;
+            self.begin_scope();
+            self.add_local(&name.lexeme);
+            self.statement(handler)?;
+            self.end_scope();
+            self.patch_jump(skip)?;
+        }
+
+        if let (Some(jump), Some(finally)) = (finally_handler, finally) {
+            self.current().exits.pop();
+            self.emit(OpCode::PopHandler);
+            self.statement(finally)?;
+            let skip = self.emit_jump(OpCode::Jump);
+
+            self.patch_jump(jump)?;
+            self.current().exits.push(Exit::Pending);
+            self.statement(finally)?;
+            self.current().exits.pop();
+            self.emit(OpCode::Rethrow);
+            self.patch_jump(skip)?;
+        }
+        Ok(())
+    }
+
+    // Leave the innermost loop or the function, cleaning up after every statement
+    // left on the way: handlers are dropped and `finally` clauses run.
+    fn jump_out(&mut self, target: Target, is_continue: bool) -> Result<()> {
+        // A returned value waits on the stack, below any local of a `finally` clause.
+        if target == Target::Function {
+            self.add_local("");
+        }
+
+        let exits = self.current().exits.clone();
+        let mut locals = 0;
+        for (i, exit) in exits.iter().enumerate().rev() {
+            match exit {
+                Exit::Loop { locals: n, .. } if target == Target::Loop => {
+                    locals = *n;
+                    break;
+                }
+                Exit::Loop { .. } => (),
+                Exit::Catch => self.emit(OpCode::PopHandler),
+                Exit::Finally(finally) => {
+                    self.emit(OpCode::PopHandler);
+                    // The clause runs as if outside of the statements it guards.
+                    let inner = self.current().exits.split_off(i);
+                    let token = self.token.clone();
+                    self.statement(finally)?;
+                    self.token = token;
+                    self.current().exits.extend(inner);
+                }
+                Exit::Pending => self.emit(OpCode::DropError),
+            }
+        }
+
+        if target == Target::Function {
+            self.current().locals.pop();
+            self.emit(OpCode::Return);
+            return Ok(());
+        }
+
+        // Discard the locals of the loop body without ending their scopes.
+        for i in (locals..self.current().locals.len()).rev() {
+            match self.current().locals[i].is_captured {
+                true => self.emit(OpCode::CloseUpvalue),
+                false => self.emit(OpCode::Pop),
+            }
+        }
+        let jump = self.emit_jump(OpCode::Jump);
+        let loop_exit = self
+            .current()
+            .exits
+            .iter_mut()
+            .rev()
+            .find(|e| matches!(e, Exit::Loop { .. }));
+        match loop_exit {
+            Some(Exit::Loop { continues, .. }) if is_continue => continues.push(jump),
+            Some(Exit::Loop { breaks, .. }) => breaks.push(jump),
+            // The parser only accepts `break` and `continue` inside loops.
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Compile a function and emit the instruction creating its closure.
+    fn function(
+        &mut self,
+        name: Option<&Token>,
+        params: &[Token],
+        body: &Stmt,
+        function_type: FunctionType,
+    ) -> Result<()> {
+        // The closure is created where the function is declared.
+        let token = self.token.clone();
+        self.functions
+            .push(FunctionCompiler::new(name, function_type));
+        self.begin_scope();
+        for param in params {
+            self.add_local(&param.lexeme);
+        }
+        self.current().function.arity = params.len();
+
+        if let Stmt::Block(stmts) = body {
+            for stmt in stmts {
+                self.statement(stmt)?;
+            }
+        }
+        self.emit_implicit_value();
+        self.emit(OpCode::Return);
+
+        let compiler = self.functions.pop().expect("Missing function compiler.");
+        self.token = token;
+        let mut function = compiler.function;
+        function.upvalue_count = compiler.upvalues.len();
+        let constant = self.make_constant(Constant::Function(Rc::new(function)))?;
+        self.emit_with(OpCode::Closure, constant);
+        for upvalue in compiler.upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_u16(upvalue.index);
+        }
+        Ok(())
+    }
+
+    // Value returned when the function ends without a value: `this` for an
+    // initializer, nil for any other function.
+    fn emit_implicit_value(&mut self) {
+        match self.current().function_type {
+            FunctionType::Initializer => self.emit_with(OpCode::GetLocal, 0),
+            _ => self.emit(OpCode::Nil),
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Assign(name, value, _depth) => {
+                self.expression(value)?;
+                self.set_variable(name)?;
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.token = operator.clone();
+                let op = match operator.token_type {
+                    TokenType::BangEqual => OpCode::NotEqual,
+                    TokenType::EqualEqual => OpCode::Equal,
+                    TokenType::Greater => OpCode::Greater,
+                    TokenType::GreaterEqual => OpCode::GreaterEqual,
+                    TokenType::Less => OpCode::Less,
+                    TokenType::LessEqual => OpCode::LessEqual,
+                    TokenType::Minus => OpCode::Subtract,
+                    TokenType::Plus => OpCode::Add,
+                    TokenType::Slash => OpCode::Divide,
+                    TokenType::Star => OpCode::Multiply,
+                    _ => unreachable!(),
+                };
+                self.emit(op);
+            }
+            Expr::Call(callee, paren, arguments) => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.token = expr.spanning(paren);
+                self.emit(OpCode::Call);
+                self.emit_byte(arguments.len() as u8);
+            }
+            Expr::Function(keyword, params, body) => {
+                self.token = keyword.clone();
+                self.function(None, params, body, FunctionType::Function)?
+            }
+            Expr::Get(object, name) => {
+                self.expression(object)?;
+                self.token = name.clone();
+                let constant = self.identifier_constant(name)?;
+                self.emit_with(OpCode::GetProperty, constant);
+            }
+            Expr::Grouping(expr) => self.expression(expr)?,
+            Expr::Index(object, bracket, index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.token = bracket.clone();
+                self.emit(OpCode::GetIndex);
+            }
+            Expr::Interpolation(start, parts) => {
+                for part in parts {
+                    self.expression(part)?;
+                }
+                self.token = start.clone();
+                let count = self.operand(parts.len(), "Too many parts in one string.")?;
+                self.emit_with(OpCode::Interpolate, count);
+            }
+            Expr::List(bracket, elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                self.token = bracket.clone();
+                let count = self.operand(elements.len(), "Too many elements in one list.")?;
+                self.emit_with(OpCode::List, count);
+            }
+            Expr::Literal(value) => match value {
+                Value::Boolean(true) => self.emit(OpCode::True),
+                Value::Boolean(false) => self.emit(OpCode::False),
+                Value::Nil => self.emit(OpCode::Nil),
+                Value::Number(n) => {
+                    let constant = self.make_constant(Constant::Number(*n))?;
+                    self.emit_with(OpCode::Constant, constant);
+                }
+                Value::String(s) => {
+                    let constant = self.make_constant(Constant::String(s.clone()))?;
+                    self.emit_with(OpCode::Constant, constant);
+                }
+                // The parser only makes literals of the values above.
+                _ => unreachable!(),
+            },
+            Expr::Logical(left, operator, right) => {
+                self.expression(left)?;
+                if operator.token_type == TokenType::Or {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                }
+            }
+            Expr::Map(brace, entries) => {
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                }
+                self.token = brace.clone();
+                let count = self.operand(entries.len(), "Too many entries in one map.")?;
+                self.emit_with(OpCode::Map, count);
+            }
+            Expr::Set(object, name, value) => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.token = name.clone();
+                let constant = self.identifier_constant(name)?;
+                self.emit_with(OpCode::SetProperty, constant);
+            }
+            Expr::SetIndex(object, bracket, index, value) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.token = bracket.clone();
+                self.emit(OpCode::SetIndex);
+            }
+            Expr::Super(keyword, method, _depth) => {
+                let this = Token::new(TokenType::This, "this", Literal::Nil, keyword.line)
+                    .with_span(keyword.span);
+                self.get_variable(&this)?;
+                self.get_variable(keyword)?;
+                self.token = method.clone();
+                let constant = self.identifier_constant(method)?;
+                self.emit_with(OpCode::GetSuper, constant);
+            }
+            Expr::This(keyword, _depth) => self.get_variable(keyword)?,
+            Expr::Unary(operator, right) => {
+                self.expression(right)?;
+                self.token = operator.clone();
+                match operator.token_type {
+                    TokenType::Bang => self.emit(OpCode::Not),
+                    TokenType::Minus => self.emit(OpCode::Negate),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Variable(name, _depth) => self.get_variable(name)?,
+        }
+        Ok(())
+    }
+
+    fn get_variable(&mut self, name: &Token) -> Result<()> {
+        self.token = name.clone();
+        let last = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(last, &name.lexeme) {
+            self.emit_with(OpCode::GetLocal, slot);
+        } else if let Some(index) = self.resolve_upvalue(last, &name.lexeme)? {
+            self.emit_with(OpCode::GetUpvalue, index);
+        } else {
+            let constant = self.identifier_constant(name)?;
+            self.emit_with(OpCode::GetGlobal, constant);
+        }
+        Ok(())
+    }
+
+    fn set_variable(&mut self, name: &Token) -> Result<()> {
+        self.token = name.clone();
+        let last = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(last, &name.lexeme) {
+            self.emit_with(OpCode::SetLocal, slot);
+        } else if let Some(index) = self.resolve_upvalue(last, &name.lexeme)? {
+            self.emit_with(OpCode::SetUpvalue, index);
+        } else {
+            let constant = self.identifier_constant(name)?;
+            self.emit_with(OpCode::SetGlobal, constant);
+        }
+        Ok(())
+    }
+
+    // Locals are declared before their initializer is compiled, which lets a
+    // function refer to itself. The resolver rejects any other such use.
+    fn declare_variable(&mut self, name: &Token) {
+        if self.current().scope_depth > 0 {
+            self.add_local(&name.lexeme);
+        }
+    }
+
+    // Bind the value on top of the stack to a declared variable.
+    // A local already lives in its slot, a global is moved to the globals.
+    fn define_variable(&mut self, name: &Token) -> Result<()> {
+        if self.current().scope_depth == 0 {
+            self.token = name.clone();
+            let constant = self.identifier_constant(name)?;
+            self.emit_with(OpCode::DefineGlobal, constant);
+        }
+        Ok(())
+    }
+
+    fn add_local(&mut self, name: &str) {
+        let depth = self.current().scope_depth;
+        self.current().locals.push(Local {
+            name: name.to_string(),
+            depth,
+            is_captured: false,
+        });
+    }
+
+    fn resolve_local(&self, function: usize, name: &str) -> Option<u16> {
+        let locals = &self.functions[function].locals;
+        let slot = locals.iter().rposition(|local| local.name == name)?;
+        Some(slot as u16)
+    }
+
+    // Index of the upvalue through which the function refers to a local of an
+    // enclosing function, adding upvalues down the chain of functions as needed.
+    fn resolve_upvalue(&mut self, function: usize, name: &str) -> Result<Option<u16>> {
+        if function == 0 {
+            return Ok(None);
+        }
+        if let Some(slot) = self.resolve_local(function - 1, name) {
+            self.functions[function - 1].locals[slot as usize].is_captured = true;
+            return self.add_upvalue(function, slot, true).map(Some);
+        }
+        match self.resolve_upvalue(function - 1, name)? {
+            Some(index) => self.add_upvalue(function, index, false).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(&mut self, function: usize, index: u16, is_local: bool) -> Result<u16> {
+        let upvalue = Upvalue { index, is_local };
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(i) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(i as u16);
+        }
+        upvalues.push(upvalue);
+        let count = upvalues.len();
+        self.operand(count - 1, "Too many closure variables in function.")
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+        let depth = self.current().scope_depth;
+        while let Some(local) = self.current().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            match local.is_captured {
+                true => self.emit(OpCode::CloseUpvalue),
+                false => self.emit(OpCode::Pop),
+            }
+            self.current().locals.pop();
+        }
+    }
+
+    fn current(&mut self) -> &mut FunctionCompiler {
+        self.functions
+            .last_mut()
+            .expect("Missing function compiler.")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().function.chunk
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let compiler = self
+            .functions
+            .last_mut()
+            .expect("Missing function compiler.");
+        compiler.function.chunk.write(byte, &self.token);
+    }
+
+    fn emit_u16(&mut self, operand: u16) {
+        for byte in operand.to_be_bytes() {
+            self.emit_byte(byte);
+        }
+    }
+
+    fn emit_with(&mut self, op: OpCode, operand: u16) {
+        self.emit(op);
+        self.emit_u16(operand);
+    }
+
+    // Emit a jump with a placeholder offset and return where the offset goes.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_with(op, u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    // Make the jump whose offset is at `offset` land on the next instruction.
+    fn patch_jump(&mut self, offset: usize) -> Result<()> {
+        let distance = self.chunk().code.len() - offset - 2;
+        let [high, low] = self
+            .operand(distance, "Too much code to jump over.")?
+            .to_be_bytes();
+        self.chunk().code[offset] = high;
+        self.chunk().code[offset + 1] = low;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<()> {
+        let distance = self.chunk().code.len() + 3 - loop_start;
+        let operand = self.operand(distance, "Loop body too large.")?;
+        self.emit_with(OpCode::Loop, operand);
+        Ok(())
+    }
+
+    fn make_constant(&mut self, constant: Constant) -> Result<u16> {
+        let index = self.chunk().add_constant(constant);
+        self.operand(index, "Too many constants in one chunk.")
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> Result<u16> {
+        self.make_constant(Constant::String(interner::intern(&name.lexeme)))
+    }
+
+    // Two-byte operand, or an error at the code being compiled if it doesn't fit.
+    fn operand(&self, value: usize, message: &str) -> Result<u16> {
+        u16::try_from(value).map_err(|_| Error::Parse(self.token.clone(), message.to_string()))
+    }
+}
@@ -0,0 +1,161 @@
+use crate::chunk::Chunk;
+use crate::chunk::Constant;
+use crate::chunk::Function;
+use crate::chunk::OpCode;
+use std::fmt::Write;
+
+// Listing of a function followed by the functions declared in it:
+//   == add ==
+//   0000    2 GetLocal            1
+//   0003    | GetLocal            2
+//   0006    | Add
+//   0007    | Return
+pub fn disassemble(function: &Function, name: &str) -> String {
+    let chunk = &function.chunk;
+    let mut listing = format!("== {} ==\n", name);
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (text, next) = instruction(chunk, offset);
+        listing.push_str(&text);
+        listing.push('\n');
+        offset = next;
+    }
+    for constant in &chunk.constants {
+        if let Constant::Function(function) = constant {
+            listing.push('\n');
+            listing.push_str(&disassemble(function, function.name()));
+        }
+    }
+    listing
+}
+
+// Text of the instruction at `offset`, with the offset of the next one.
+// The line is replaced by `|` when it is the line of the previous instruction.
+pub fn instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let line = chunk.token(offset).line;
+    let mut text = match offset > 0 && chunk.token(offset - 1).line == line {
+        true => format!("{:04}    | ", offset),
+        false => format!("{:04} {:>4} ", offset, line),
+    };
+
+    let byte = chunk.code[offset];
+    let Some(op) = OpCode::from_byte(byte) else {
+        text.push_str(&format!("Unknown opcode {}", byte));
+        return (text, offset + 1);
+    };
+    let name = format!("{:?}", op);
+    let next = match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Import => {
+            let index = chunk.read_u16(offset + 1);
+            write_constant(&mut text, &name, chunk, index);
+            offset + 3
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::List
+        | OpCode::Map
+        | OpCode::Interpolate => {
+            let operand = chunk.read_u16(offset + 1);
+            let _ = write!(text, "{:<16} {:4}", name, operand);
+            offset + 3
+        }
+        OpCode::Call => {
+            let _ = write!(text, "{:<16} {:4}", name, chunk.code[offset + 1]);
+            offset + 2
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::PushCatch | OpCode::PushFinally => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+            let _ = write!(text, "{:<16} {:4} -> {}", name, offset, offset + 3 + jump);
+            offset + 3
+        }
+        OpCode::Loop => {
+            let jump = chunk.read_u16(offset + 1) as usize;
+            let _ = write!(text, "{:<16} {:4} -> {}", name, offset, offset + 3 - jump);
+            offset + 3
+        }
+        OpCode::Class | OpCode::Subclass => {
+            let index = chunk.read_u16(offset + 1);
+            write_constant(&mut text, &name, chunk, index);
+            let _ = write!(text, " ({} methods)", chunk.read_u16(offset + 3));
+            offset + 5
+        }
+        OpCode::Closure => {
+            let index = chunk.read_u16(offset + 1);
+            write_constant(&mut text, &name, chunk, index);
+            let mut next = offset + 3;
+            if let Some(Constant::Function(function)) = chunk.constants.get(index as usize) {
+                // Each captured variable follows as a flag and an index.
+                for _ in 0..function.upvalue_count {
+                    let kind = match chunk.code[next] {
+                        1 => "local",
+                        _ => "upvalue",
+                    };
+                    let index = chunk.read_u16(next + 1);
+                    let _ = write!(text, "\n{:04}    |   {} {}", next, kind, index);
+                    next += 3;
+                }
+            }
+            next
+        }
+        _ => {
+            text.push_str(&name);
+            offset + 1
+        }
+    };
+    (text, next)
+}
+
+fn write_constant(text: &mut String, name: &str, chunk: &Chunk, index: u16) {
+    let _ = match chunk.constants.get(index as usize) {
+        Some(constant) => write!(text, "{:<16} {:4} '{}'", name, index, constant),
+        None => write!(text, "{:<16} {:4} <missing>", name, index),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::lox;
+
+    fn listing(source: &str) -> String {
+        let stmts = lox::parse(source).unwrap();
+        let function = Compiler::new().run(&stmts).unwrap();
+        disassemble(&function, "script")
+    }
+
+    #[test]
+    fn disassemble_script() {
+        assert_eq!(
+            listing("var a = 1;\nprint a + 2;"),
+            "== script ==\n\
+             0000    1 Constant            0 '1'\n\
+             0003    | DefineGlobal        1 'a'\n\
+             0006    2 GetGlobal           1 'a'\n\
+             0009    | Constant            2 '2'\n\
+             0012    | Add\n\
+             0013    | Print\n\
+             0014    | Nil\n\
+             0015    | Return\n"
+        );
+    }
+
+    #[test]
+    fn disassemble_nested_functions() {
+        let listing = listing("fun outer() {\n  var x = 1;\n  fun inner() { return x; }\n}");
+        assert!(listing.contains("== outer ==\n"));
+        assert!(
+            listing.contains("0003    3 Closure             1 '<fn inner>'\n0006    |   local 1")
+        );
+        assert!(listing.contains("== inner ==\n0000    3 GetUpvalue          0\n"));
+    }
+}
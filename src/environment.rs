@@ -1,15 +1,20 @@
+use crate::gc::Trace;
+use crate::gc::Tracer;
+use crate::interner;
 use crate::result::Error;
 use crate::result::Result;
 use crate::token::Token;
 use crate::value::Value;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::mem;
 use std::rc::Rc;
 
 #[derive(Clone, Debug)]
 pub struct Environment {
     enclosing: Option<Rc<RefCell<Environment>>>,
-    values: RefCell<HashMap<String, Value>>,
+    // Names are interned, so defining a variable doesn't copy its name.
+    values: RefCell<HashMap<Rc<str>, Value>>,
 }
 
 impl Default for Environment {
@@ -34,33 +39,119 @@ impl Environment {
     }
 
     pub fn define(&self, name: &str, value: Value) -> Result<()> {
-        self.values.borrow_mut().insert(name.to_string(), value);
+        self.values
+            .borrow_mut()
+            .insert(interner::intern(name), value);
         Ok(())
     }
 
     pub fn get(&self, name: &Token) -> Result<Value> {
-        if let Some(value) = self.values.borrow().get(&name.lexeme) {
-            return Ok(value.clone());
+        self.lookup(&name.lexeme).ok_or_else(|| {
+            Error::Runtime(
+                name.clone(),
+                format!("Undefined variable '{}'.", name.lexeme),
+            )
+        })
+    }
+
+    pub fn assign(&self, name: &Token, value: Value) -> Result<()> {
+        match self.update(&name.lexeme, value) {
+            true => Ok(()),
+            false => Err(Error::Runtime(
+                name.clone(),
+                format!("Undefined variable '{}'.", name.lexeme),
+            )),
         }
-        if let Some(enclosing) = &self.enclosing {
-            return enclosing.borrow().get(name);
+    }
+
+    // Look up a variable here or up the chain.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.values.borrow().get(name) {
+            return Some(value.clone());
+        }
+        self.enclosing.as_ref()?.borrow().lookup(name)
+    }
+
+    // Assign a variable here or up the chain, telling whether it was found.
+    pub fn update(&self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.borrow_mut().get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().update(name, value),
+            None => false,
+        }
+    }
+
+    // Look up a variable defined directly in this environment.
+    pub fn get_local(&self, name: &str) -> Option<Value> {
+        self.values.borrow().get(name).cloned()
+    }
+
+    // Look up a variable in the environment `distance` hops up the chain.
+    pub fn get_at(&self, distance: usize, name: &Token) -> Result<Value> {
+        if distance == 0 {
+            if let Some(value) = self.values.borrow().get(name.lexeme.as_str()) {
+                return Ok(value.clone());
+            }
+        } else if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow().get_at(distance - 1, name);
         }
         Err(Error::Runtime(
             name.clone(),
-            format!("Undefined variable '{}'", name.lexeme),
+            format!("Undefined variable '{}'.", name.lexeme),
         ))
     }
 
-    pub fn assign(&self, name: &Token, value: Value) -> Result<()> {
-        if self.values.borrow().contains_key(&name.lexeme) {
+    // Assign a variable in the environment `distance` hops up the chain.
+    pub fn assign_at(&self, distance: usize, name: &Token, value: Value) -> Result<()> {
+        if distance == 0 {
+            if let Some(slot) = self.values.borrow_mut().get_mut(name.lexeme.as_str()) {
+                *slot = value;
+                return Ok(());
+            }
             return self.define(&name.lexeme, value);
         }
         if let Some(enclosing) = &self.enclosing {
-            return enclosing.borrow_mut().assign(name, value);
+            return enclosing.borrow().assign_at(distance - 1, name, value);
         }
         Err(Error::Runtime(
             name.clone(),
-            format!("Undefined variable '{}'", name.lexeme),
+            format!("Undefined variable '{}'.", name.lexeme),
         ))
     }
 }
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, tracer: &mut Tracer) {
+        // An environment being changed is also held from outside the heap.
+        let Ok(environment) = self.try_borrow() else {
+            return;
+        };
+        if let Some(enclosing) = &environment.enclosing {
+            tracer.mark(enclosing);
+        }
+        if let Ok(values) = environment.values.try_borrow() {
+            values.values().for_each(|v| tracer.mark_value(v));
+        };
+    }
+
+    fn clear(&self) {
+        if let Ok(environment) = self.try_borrow() {
+            let values = environment
+                .values
+                .try_borrow_mut()
+                .map(|mut v| mem::take(&mut *v));
+            drop(values);
+        }
+    }
+
+    fn size(&self) -> usize {
+        let entries = match self.try_borrow() {
+            Ok(environment) => environment.values.try_borrow().map_or(0, |v| v.capacity()),
+            Err(_) => 0,
+        };
+        mem::size_of::<Self>() + entries * mem::size_of::<(Rc<str>, Value)>()
+    }
+}
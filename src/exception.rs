@@ -0,0 +1,68 @@
+use crate::gc::Trace;
+use crate::gc::Tracer;
+use crate::result::Error;
+use crate::result::Result;
+use crate::token::Token;
+use crate::value::Value;
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+// Runtime error caught by a `catch` clause
+#[derive(Debug)]
+pub struct LoxException {
+    message: String,
+    line: usize,
+}
+
+impl LoxException {
+    pub fn new(message: &str, line: usize) -> Self {
+        Self {
+            message: message.to_string(),
+            line,
+        }
+    }
+
+    // Exceptions expose `message` and `line` as read-only properties.
+    pub fn get(&self, name: &Token) -> Result<Value> {
+        match name.lexeme.as_str() {
+            "message" => Ok(Value::from(self.message.as_str())),
+            "line" => Ok(Value::Number(self.line as f64)),
+            _ => Err(Error::Runtime(
+                name.clone(),
+                format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LoxException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// Exceptions are only equal to themselves
+impl cmp::PartialEq for LoxException {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for LoxException {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+// Exceptions hold no values yet, but are counted with the rest of the heap.
+impl Trace for LoxException {
+    fn trace(&self, _tracer: &mut Tracer) {}
+
+    fn clear(&self) {}
+
+    fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.message.capacity()
+    }
+}
@@ -1,17 +1,90 @@
+use crate::stmt::Stmt;
+use crate::token::Span;
 use crate::token::Token;
 use crate::value::Value;
 use std::fmt;
 
 #[derive(Clone, Debug)]
 pub enum Expr {
-    Assign(Token, Box<Expr>),
+    Assign(Token, Box<Expr>, Option<usize>),
     Binary(Box<Expr>, Token, Box<Expr>),
     Call(Box<Expr>, Token, Vec<Expr>),
+    // `fun` keyword, parameters and body of an anonymous function
+    Function(Token, Vec<Token>, Box<Stmt>),
+    Get(Box<Expr>, Token),
     Grouping(Box<Expr>),
+    // Indexed object, `[` and index
+    Index(Box<Expr>, Token, Box<Expr>),
+    // Opening part of the string, then alternating string parts and expressions
+    Interpolation(Token, Vec<Expr>),
+    // `[` and elements
+    List(Token, Vec<Expr>),
     Literal(Value),
     Logical(Box<Expr>, Token, Box<Expr>),
+    // `{` and key-value pairs
+    Map(Token, Vec<(Expr, Expr)>),
+    Set(Box<Expr>, Token, Box<Expr>),
+    // Indexed object, `[`, index and assigned value
+    SetIndex(Box<Expr>, Token, Box<Expr>, Box<Expr>),
+    Super(Token, Token, Option<usize>),
+    This(Token, Option<usize>),
     Unary(Token, Box<Expr>),
-    Variable(Token),
+    Variable(Token, Option<usize>),
+}
+
+impl Expr {
+    // Location of the whole expression in the source.
+    // Only tokens store a span; nodes derive theirs from the tokens they hold.
+    // Literals carry no token, so an expression made only of literals has no span.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::Assign(name, value, _) => merge(Some(name.span), value.span()),
+            Expr::Binary(left, operator, right) | Expr::Logical(left, operator, right) => {
+                merge(merge(left.span(), Some(operator.span)), right.span())
+            }
+            Expr::Call(callee, paren, _) => merge(callee.span(), Some(paren.span)),
+            Expr::Function(keyword, _, _) => Some(keyword.span),
+            Expr::Get(object, name) => merge(object.span(), Some(name.span)),
+            Expr::Grouping(expr) => expr.span(),
+            Expr::Index(object, bracket, index) => {
+                merge(merge(object.span(), Some(bracket.span)), index.span())
+            }
+            Expr::Interpolation(start, parts) => merge(
+                Some(start.span),
+                parts.iter().rev().find_map(|part| part.span()),
+            ),
+            Expr::List(bracket, elements) => {
+                merge(Some(bracket.span), elements.last().and_then(|e| e.span()))
+            }
+            Expr::Literal(_) => None,
+            Expr::Map(brace, entries) => merge(
+                Some(brace.span),
+                entries.last().and_then(|(_, value)| value.span()),
+            ),
+            Expr::Set(object, _name, value) => merge(object.span(), value.span()),
+            Expr::SetIndex(object, _bracket, _index, value) => merge(object.span(), value.span()),
+            Expr::Super(keyword, method, _) => Some(keyword.span.merge(&method.span)),
+            Expr::This(keyword, _) => Some(keyword.span),
+            Expr::Unary(operator, right) => merge(Some(operator.span), right.span()),
+            Expr::Variable(name, _) => Some(name.span),
+        }
+    }
+
+    // `token` widened to the whole expression, used to point errors of a call
+    // at all of it rather than only at its closing parenthesis.
+    pub fn spanning(&self, token: &Token) -> Token {
+        match self.span() {
+            Some(span) => token.clone().with_span(span),
+            None => token.clone(),
+        }
+    }
+}
+
+fn merge(a: Option<Span>, b: Option<Span>) -> Option<Span> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.merge(&b)),
+        (a, b) => a.or(b),
+    }
 }
 
 impl fmt::Display for Expr {
@@ -22,7 +95,7 @@ impl fmt::Display for Expr {
 
 fn format_ast(expr: &Expr) -> String {
     match expr {
-        Expr::Assign(name, value) => format!("(= {} {})", name.lexeme, value),
+        Expr::Assign(name, value, _depth) => format!("(= {} {})", name.lexeme, value),
         Expr::Binary(left, operator, right) => format!(
             "({} {} {})",
             operator.lexeme,
@@ -36,7 +109,23 @@ fn format_ast(expr: &Expr) -> String {
             }
             format!("(call {} {})", callee, str_args.join(" "))
         }
+        Expr::Function(_keyword, params, body) => {
+            let params: Vec<String> = params.iter().map(|t| t.lexeme.to_string()).collect();
+            format!("(fun ({}) {})", params.join(" "), body)
+        }
+        Expr::Get(object, name) => format!("(. {} {})", format_ast(object), name.lexeme),
         Expr::Grouping(expr) => format!("(group {})", format_ast(expr)),
+        Expr::Index(object, _bracket, index) => {
+            format!("([] {} {})", format_ast(object), format_ast(index))
+        }
+        Expr::Interpolation(_start, parts) => {
+            let parts: Vec<String> = parts.iter().map(format_ast).collect();
+            format!("(str {})", parts.join(" "))
+        }
+        Expr::List(_bracket, elements) => {
+            let elements: Vec<String> = elements.iter().map(format_ast).collect();
+            format!("(list {})", elements.join(" "))
+        }
         Expr::Literal(value) => value.to_string(),
         Expr::Logical(left, operator, right) => format!(
             "({} {} {})",
@@ -44,8 +133,29 @@ fn format_ast(expr: &Expr) -> String {
             operator.lexeme,
             format_ast(right)
         ),
+        Expr::Map(_brace, entries) => {
+            let entries: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("({} {})", format_ast(key), format_ast(value)))
+                .collect();
+            format!("(map {})", entries.join(" "))
+        }
+        Expr::Set(object, name, value) => format!(
+            "(= (. {} {}) {})",
+            format_ast(object),
+            name.lexeme,
+            format_ast(value)
+        ),
+        Expr::SetIndex(object, _bracket, index, value) => format!(
+            "(= ([] {} {}) {})",
+            format_ast(object),
+            format_ast(index),
+            format_ast(value)
+        ),
+        Expr::Super(_keyword, method, _depth) => format!("(super {})", method.lexeme),
+        Expr::This(_keyword, _depth) => "this".to_string(),
         Expr::Unary(operator, right) => format!("({} {})", operator.lexeme, format_ast(right)),
-        Expr::Variable(name) => name.lexeme.clone(),
+        Expr::Variable(name, _depth) => name.lexeme.clone(),
     }
 }
 
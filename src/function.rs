@@ -1,7 +1,11 @@
+use crate::callable::Arity;
 use crate::callable::Callable;
+use crate::class::LoxInstance;
 use crate::environment::Environment;
+use crate::gc;
+use crate::gc::Tracer;
+use crate::interpreter::ControlFlow;
 use crate::interpreter::Interpreter;
-use crate::result::Error;
 use crate::result::Result;
 use crate::stmt::Stmt;
 use crate::token::Token;
@@ -9,40 +13,78 @@ use crate::value::Value;
 use std::cell::RefCell;
 use std::cmp;
 use std::fmt;
+use std::mem;
 use std::rc::Rc;
 
 // User-defined function
 #[derive(Clone, Debug)]
 pub struct LoxFunction {
-    name: String,
+    // None for anonymous functions
+    name: Option<String>,
     params: Vec<Token>,
     body: Vec<Stmt>,
     closure: Rc<RefCell<Environment>>,
+    // Top-level bindings of the file the function was declared in
+    globals: Rc<RefCell<Environment>>,
+    is_initializer: bool,
 }
 
 impl LoxFunction {
     pub fn new(
-        name: &Token,
+        name: Option<&Token>,
         params: &[Token],
         body: &Stmt,
         closure: Rc<RefCell<Environment>>,
+        globals: Rc<RefCell<Environment>>,
+        is_initializer: bool,
     ) -> Self {
         let block = match body {
             Stmt::Block(ref stmts) => stmts,
             _ => unreachable!(),
         };
         Self {
-            name: name.lexeme.clone(),
+            name: name.map(|n| n.lexeme.clone()),
             params: params.to_vec(),
             body: block.to_vec(),
             closure,
+            globals,
+            is_initializer,
         }
     }
+
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("anonymous")
+    }
+
+    // Create a method whose closure binds `this` to the instance.
+    pub fn bind(&self, instance: Rc<LoxInstance>) -> Result<Self> {
+        let environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define("this", Value::LoxInstance(instance))?;
+        Ok(Self {
+            closure: gc::alloc(RefCell::new(environment)),
+            ..self.clone()
+        })
+    }
+
+    // Functions are copied by value, so the environments they hold are
+    // references of whatever holds the function.
+    pub fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.closure);
+        tracer.mark(&self.globals);
+    }
+
+    // An initializer always returns `this`.
+    fn this(&self) -> Value {
+        self.closure
+            .borrow()
+            .get_local("this")
+            .unwrap_or(Value::Nil)
+    }
 }
 
 impl Callable for LoxFunction {
-    fn arity(&self) -> usize {
-        self.params.len()
+    fn arity(&self) -> Arity {
+        Arity::Fixed(self.params.len())
     }
 
     fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
@@ -54,25 +96,38 @@ impl Callable for LoxFunction {
             environment.define(&param.lexeme, arg.clone())?;
         }
 
+        // Globals are looked up in the file that declared the function.
+        let previous_globals = mem::replace(&mut interpreter.globals, Rc::clone(&self.globals));
+
         // Execute block statement
-        interpreter.environment = Rc::new(RefCell::new(environment));
+        interpreter.environment = gc::alloc(RefCell::new(environment));
+        let mut result = Ok(Value::Nil);
         for stmt in &self.body {
-            if let Err(error) = interpreter.execute(stmt) {
-                interpreter.environment = previous;
-                match error {
-                    Error::Return(value) => return Ok(value),
-                    _ => return Err(error),
+            match interpreter.execute(stmt) {
+                Ok(ControlFlow::Return(value)) => {
+                    result = Ok(value);
+                    break;
+                }
+                // The parser keeps `break` and `continue` inside loops.
+                Ok(_) => (),
+                Err(error) => {
+                    result = Err(error);
+                    break;
                 }
             }
         }
         interpreter.environment = previous;
-        Ok(Value::Nil)
+        interpreter.globals = previous_globals;
+        if self.is_initializer && result.is_ok() {
+            return Ok(self.this());
+        }
+        result
     }
 }
 
 impl fmt::Display for LoxFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "<fn {}>", self.name)
+        write!(f, "<fn {}>", self.name())
     }
 }
 
@@ -88,21 +143,29 @@ impl PartialOrd for LoxFunction {
     }
 }
 
+// Signature of a primitive function implemented in Rust
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value>;
+
 // Primitive function
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[derive(Clone)]
 pub struct NativeFunction {
-    arity: usize,
-    function: fn(&mut Interpreter, &[Value]) -> Result<Value>,
+    name: String,
+    arity: Arity,
+    function: Rc<NativeFn>,
 }
 
 impl NativeFunction {
-    pub fn new(arity: usize, function: fn(&mut Interpreter, &[Value]) -> Result<Value>) -> Self {
-        Self { arity, function }
+    pub fn new(name: &str, arity: Arity, function: Rc<NativeFn>) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            function,
+        }
     }
 }
 
 impl Callable for NativeFunction {
-    fn arity(&self) -> usize {
+    fn arity(&self) -> Arity {
         self.arity
     }
 
@@ -116,3 +179,24 @@ impl fmt::Display for NativeFunction {
         write!(f, "<native fn>")
     }
 }
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl cmp::PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.function, &other.function)
+    }
+}
+
+impl PartialOrd for NativeFunction {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        self.name.partial_cmp(&other.name)
+    }
+}
@@ -0,0 +1,284 @@
+use crate::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+use std::rc::Weak;
+
+// Objects are reference counted, which frees them as soon as nothing refers to
+// them, except when they refer to each other in a cycle, e.g. a function stored
+// in the environment it closes over. The collector finds such cycles among the
+// objects allocated with `alloc` and breaks them.
+//
+// Roots are found by counting: an object with more references than the other
+// objects hold is also referred to from outside the heap, i.e. by the
+// interpreter, the virtual machine, a Rust caller or the host. This makes it
+// safe to collect in the middle of running code.
+//
+// The heap belongs to the thread, and is shared by every `Lox` running on it.
+
+// Fewest objects to allocate before collecting
+const MIN_GC: usize = 1024;
+
+// Heap object that can refer to other heap objects
+pub trait Trace {
+    // Report every reference this object holds to a heap object.
+    fn trace(&self, tracer: &mut Tracer);
+
+    // Drop the values this object holds, once it is known to be garbage.
+    fn clear(&self);
+
+    // Approximate size in bytes, including what the object owns.
+    fn size(&self) -> usize;
+}
+
+// References found while tracing an object, by address
+#[derive(Default)]
+pub struct Tracer {
+    edges: Vec<usize>,
+}
+
+impl Tracer {
+    pub fn mark<T: ?Sized>(&mut self, object: &Rc<T>) {
+        self.edges.push(address(object));
+    }
+
+    pub fn mark_value(&mut self, value: &Value) {
+        match value {
+            Value::LoxFunction(function) => function.trace(self),
+            Value::LoxClass(class) => self.mark(class),
+            Value::LoxInstance(instance) => self.mark(instance),
+            Value::List(list) => self.mark(list),
+            Value::Map(map) => self.mark(map),
+            Value::Module(module) => self.mark(module),
+            Value::Closure(closure) => self.mark(closure),
+            Value::BoundMethod(method) => self.mark(method),
+            Value::Exception(exception) => self.mark(exception),
+            Value::Boolean(_) | Value::Number(_) | Value::String(_) | Value::Nil => (),
+            // What a native closure captured can't be seen, so it stays alive
+            // as long as the native, even in a cycle.
+            Value::NativeFunction(_) => (),
+        }
+    }
+}
+
+// Memory use of the heap
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    // Objects alive
+    pub objects: usize,
+    // Approximate bytes used by the objects alive
+    pub bytes: usize,
+    // Collections run so far
+    pub collections: usize,
+    // Objects freed by collections so far
+    pub freed: usize,
+}
+
+struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    // Number of objects at which freed ones are forgotten
+    next_prune: usize,
+    // Number of objects at which to collect
+    next_gc: usize,
+    collections: usize,
+    freed: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap {
+        objects: Vec::new(),
+        next_prune: MIN_GC,
+        next_gc: MIN_GC,
+        collections: 0,
+        freed: 0,
+    });
+}
+
+fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+// Allocate an object managed by the collector.
+pub fn alloc<T: Trace + 'static>(object: T) -> Rc<T> {
+    let object = Rc::new(object);
+    let weak: Weak<dyn Trace> = Rc::downgrade(&object) as Weak<dyn Trace>;
+    HEAP.with_borrow_mut(|heap| {
+        heap.objects.push(weak);
+        // Objects freed by reference counting still take up a slot.
+        if heap.objects.len() >= heap.next_prune {
+            heap.objects.retain(|o| o.strong_count() > 0);
+            heap.next_prune = MIN_GC.max(heap.objects.len() * 2);
+        }
+    });
+    object
+}
+
+// Collect if enough objects were allocated since the last collection.
+// Called at every call and loop iteration of both interpreters, so that cycles
+// made in a loop are freed before the loop ends; when no collection is due it
+// only compares two counters.
+pub fn maybe_collect() {
+    if HEAP.with_borrow(|heap| heap.objects.len() >= heap.next_gc) {
+        collect();
+    }
+}
+
+// Free every object only reachable from garbage cycles, e.g. a closure stored
+// in the environment it captures, and return what is left. This covers every
+// `Lox` of the thread.
+// Unlike a tracing collector, this does not ask the interpreter or the VM for
+// their roots. Any object with more references than the heap gives it is held
+// from outside, by an interpreter, a VM stack or the host, and counts as a root.
+// This finds roots the interpreters cannot list, such as values held by natives
+// or by Rust code embedding `Lox`, at the cost of tracing the whole heap.
+pub fn collect() -> Stats {
+    let objects: Vec<Rc<dyn Trace>> = HEAP.with_borrow_mut(|heap| {
+        heap.objects.retain(|o| o.strong_count() > 0);
+        heap.objects.iter().filter_map(Weak::upgrade).collect()
+    });
+    let index: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| (address(object), i))
+        .collect();
+
+    // Count the references each object gets from the others.
+    let mut edges = Vec::with_capacity(objects.len());
+    let mut internal = vec![0; objects.len()];
+    for object in &objects {
+        let mut tracer = Tracer::default();
+        object.trace(&mut tracer);
+        let targets: Vec<usize> = tracer
+            .edges
+            .iter()
+            .filter_map(|a| index.get(a).copied())
+            .collect();
+        for &target in &targets {
+            internal[target] += 1;
+        }
+        edges.push(targets);
+    }
+
+    // Mark from the roots. `objects` holds one reference to each object itself.
+    let mut reachable = vec![false; objects.len()];
+    let mut stack = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        if Rc::strong_count(object) - 1 > internal[i] {
+            reachable[i] = true;
+            stack.push(i);
+        }
+    }
+    while let Some(i) = stack.pop() {
+        for &target in &edges[i] {
+            if !reachable[target] {
+                reachable[target] = true;
+                stack.push(target);
+            }
+        }
+    }
+
+    // Emptying the garbage breaks its cycles, so dropping it frees it.
+    let mut freed = 0;
+    for (object, reachable) in objects.iter().zip(reachable) {
+        if !reachable {
+            object.clear();
+            freed += 1;
+        }
+    }
+    mem::drop(objects);
+
+    HEAP.with_borrow_mut(|heap| {
+        heap.objects.retain(|o| o.strong_count() > 0);
+        heap.collections += 1;
+        heap.freed += freed;
+        heap.next_gc = MIN_GC.max(heap.objects.len() * 2);
+        heap.next_prune = heap.next_prune.max(heap.next_gc);
+    });
+    stats()
+}
+
+// Objects alive and the memory they use, for every `Lox` of the thread.
+pub fn stats() -> Stats {
+    HEAP.with_borrow(|heap| {
+        let live: Vec<Rc<dyn Trace>> = heap.objects.iter().filter_map(Weak::upgrade).collect();
+        Stats {
+            objects: live.len(),
+            bytes: live.iter().map(|o| o.size()).sum(),
+            collections: heap.collections,
+            freed: heap.freed,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Backend;
+    use crate::lox::Lox;
+
+    // Run `test` on a fresh `Lox` for each backend.
+    fn on_each_backend(test: impl Fn(&mut Lox, Backend)) {
+        for backend in [Backend::TreeWalker, Backend::Vm] {
+            let mut lox = Lox::new();
+            lox.set_backend(backend);
+            test(&mut lox, backend);
+        }
+    }
+
+    // Functions closing over the environment they are stored in
+    const CYCLES: &str = "
+        for (var i = 0; i < 100; i = i + 1) {
+          fun f() { return f; }
+          var list = [];
+          push(list, list);
+        }";
+
+    #[test]
+    fn free_cycles() {
+        on_each_backend(|lox, backend| {
+            let before = collect();
+            lox.run(CYCLES).unwrap();
+            let after = collect();
+            assert_eq!(after.objects, before.objects, "{:?}", backend);
+            assert!(after.freed >= before.freed + 200, "{:?}", backend);
+        });
+    }
+
+    #[test]
+    fn count_exceptions() {
+        on_each_backend(|lox, backend| {
+            lox.run("var e; try { -nil; } catch (error) { e = error; }")
+                .unwrap();
+            let before = collect();
+            lox.run("e = nil;").unwrap();
+            let after = collect();
+            assert_eq!(after.objects + 1, before.objects, "{:?}", backend);
+        });
+    }
+
+    #[test]
+    fn keep_reachable_objects() {
+        on_each_backend(|lox, _| {
+            lox.run(
+                "fun counter() { var n = 0; fun next() { n = n + 1; return n; } return next; }
+                 var next = counter();
+                 class Node { init() { this.self = this; } }
+                 var node = Node();",
+            )
+            .unwrap();
+            let host = lox.eval("var list = [1]; push(list, list); list;").unwrap();
+            lox.run("list = nil; next();").unwrap();
+            collect();
+            assert_eq!(lox.eval("next();").unwrap(), Value::Number(2.0));
+            assert_eq!(
+                lox.eval("node.self == node;").unwrap(),
+                Value::Boolean(true)
+            );
+            let Value::List(list) = host else {
+                panic!("Expected a list, got {}", host)
+            };
+            assert_eq!(list.len(), 2);
+        });
+    }
+}
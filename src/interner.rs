@@ -0,0 +1,63 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// Names and string literals are shared, with a single copy of each content, so
+// that defining a variable doesn't copy its name and comparing two of them
+// usually stops at their addresses.
+
+// Fewest strings to intern before forgetting unused ones
+const MIN_PRUNE: usize = 1024;
+
+struct Interner {
+    strings: HashSet<Rc<str>>,
+    // Number of strings at which the ones only held here are dropped
+    next_prune: usize,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+        strings: HashSet::new(),
+        next_prune: MIN_PRUNE,
+    });
+}
+
+// The shared copy of a string, made the first time the string is seen.
+pub fn intern(s: &str) -> Rc<str> {
+    INTERNER.with_borrow_mut(|interner| {
+        if let Some(interned) = interner.strings.get(s) {
+            return Rc::clone(interned);
+        }
+        if interner.strings.len() >= interner.next_prune {
+            interner.strings.retain(|s| Rc::strong_count(s) > 1);
+            interner.next_prune = MIN_PRUNE.max(interner.strings.len() * 2);
+        }
+        let interned: Rc<str> = Rc::from(s);
+        interner.strings.insert(Rc::clone(&interned));
+        interned
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_equal_strings() {
+        let a = intern("lox");
+        let b = intern(&(String::from("lo") + "x"));
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &intern("lux")));
+    }
+
+    #[test]
+    fn forget_unused_strings() {
+        let kept = intern("kept");
+        for i in 0..MIN_PRUNE * 4 {
+            intern(&i.to_string());
+        }
+        let count = INTERNER.with_borrow(|interner| interner.strings.len());
+        assert!(count < MIN_PRUNE * 2, "{}", count);
+        assert!(Rc::ptr_eq(&kept, &intern("kept")));
+    }
+}
@@ -1,20 +1,93 @@
-use crate::callable::Callable;
+use crate::callable::Arity;
+use crate::chunk::Function;
+use crate::class::LoxClass;
+use crate::class::LoxInstance;
+use crate::compiler::Compiler;
 use crate::environment::Environment;
+use crate::exception::LoxException;
 use crate::expr::Expr;
 use crate::function::LoxFunction;
 use crate::function::NativeFunction;
+use crate::gc;
+use crate::list;
+use crate::literal::Literal;
+use crate::lox;
+use crate::map;
+use crate::map::LoxMap;
+use crate::module::LoxModule;
 use crate::result::Error;
 use crate::result::Result;
 use crate::stmt::Stmt;
+use crate::token::Token;
 use crate::token_type::TokenType;
 use crate::value::Value;
+use crate::vm::Vm;
+use crate::vm::FRAMES_MAX;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::default::Default;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::iter;
+use std::mem;
+use std::path::Path;
+use std::path::PathBuf;
 use std::rc::Rc;
 
+// Native stack a Lox call may need before the stack is grown, ample in a debug build
+const STACK_RED_ZONE: usize = 256 * 1024;
+// Native stack added each time it is grown
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
 pub struct Interpreter {
+    // Natives, shared by the script and every module it imports
+    builtins: Rc<RefCell<Environment>>,
+    // Top-level bindings of the file being run
     pub globals: Rc<RefCell<Environment>>,
     pub environment: Rc<RefCell<Environment>>,
+    // Destination of `print` statements
+    pub(crate) output: Box<dyn Write>,
+    // Calls to Lox functions in progress, innermost last
+    pub(crate) frames: Vec<Frame>,
+    // Frames active when the last uncaught runtime error was raised.
+    // Cleared when a `catch` handles the error, as it no longer ends the script.
+    pub(crate) trace: Option<Vec<Frame>>,
+    // Innermost call being evaluated, where errors from natives are reported
+    pub(crate) call_site: Option<Token>,
+    // Files being run, the script first and the module being imported last
+    files: Vec<PathBuf>,
+    // Modules already imported, by canonical path
+    modules: HashMap<PathBuf, Rc<LoxModule>>,
+    // How scripts and modules are run
+    backend: Backend,
+}
+
+// Way of running code
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backend {
+    // Walk the syntax tree
+    #[default]
+    TreeWalker,
+    // Compile to bytecode and run it on a stack machine
+    Vm,
+}
+
+// How a statement finished, so that loops and functions can be left early.
+// Kept apart from `Error`, which only holds diagnostics.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ControlFlow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+// A call to a Lox function, for tracebacks
+#[derive(Clone, Debug)]
+pub(crate) struct Frame {
+    pub(crate) function: String,
+    pub(crate) call_site: Token,
 }
 
 impl Default for Interpreter {
@@ -25,8 +98,26 @@ impl Default for Interpreter {
 
 impl Interpreter {
     pub fn new() -> Self {
-        let environment = Environment::new();
-        let globals = Rc::new(RefCell::new(environment));
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let builtins = gc::alloc(RefCell::new(Environment::new()));
+        let environment = Environment::with_enclosing(Rc::clone(&builtins));
+        let globals = gc::alloc(RefCell::new(environment));
+
+        let mut interpreter = Self {
+            builtins,
+            globals: Rc::clone(&globals),
+            environment: globals,
+            output,
+            frames: Vec::new(),
+            trace: None,
+            call_site: None,
+            files: Vec::new(),
+            modules: HashMap::new(),
+            backend: Backend::default(),
+        };
 
         // Define a primitive function
         fn clock(_: &mut Interpreter, _: &[Value]) -> Result<Value> {
@@ -37,16 +128,39 @@ impl Interpreter {
             let milliseconds = current_time.as_millis() as f64;
             Ok(Value::Number(milliseconds / 1000.0))
         }
-        let function = NativeFunction::new(0, clock);
-        globals
-            .borrow_mut()
-            .define("clock", Value::NativeFunction(function))
+        interpreter
+            .define_native("clock", 0, clock)
             .expect("Failed to define a primitive function.");
+        list::define_natives(&mut interpreter).expect("Failed to define list functions.");
+        map::define_natives(&mut interpreter).expect("Failed to define map functions.");
 
-        Self {
-            globals: Rc::clone(&globals),
-            environment: globals,
-        }
+        interpreter
+    }
+
+    // Register a primitive function as a global.
+    pub fn define_native<F>(
+        &mut self,
+        name: &str,
+        arity: impl Into<Arity>,
+        function: F,
+    ) -> Result<()>
+    where
+        F: Fn(&mut Interpreter, &[Value]) -> Result<Value> + 'static,
+    {
+        let function = NativeFunction::new(name, arity.into(), Rc::new(function));
+        self.builtins
+            .borrow_mut()
+            .define(name, Value::NativeFunction(function))
+    }
+
+    // Resolve imports relative to the script at `path`.
+    pub fn set_script_path(&mut self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.files = vec![path];
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
     }
 
     pub fn run(&mut self, statements: &[Stmt]) -> Result<()> {
@@ -56,11 +170,236 @@ impl Interpreter {
         Ok(())
     }
 
+    // Execute statements and return the value of the last one if it is an expression.
+    pub fn eval(&mut self, statements: &[Stmt]) -> Result<Value> {
+        self.trace = None;
+        if self.backend == Backend::Vm {
+            let function = Compiler::new().run(statements)?;
+            return self.run_compiled(function);
+        }
+        let Some((last, rest)) = statements.split_last() else {
+            return Ok(Value::Nil);
+        };
+        self.run(rest)?;
+        match last {
+            Stmt::Expression(expr) => self.evaluate(expr),
+            _ => {
+                self.execute(last)?;
+                Ok(Value::Nil)
+            }
+        }
+    }
+
+    // Run a compiled script on the virtual machine.
+    pub(crate) fn run_compiled(&mut self, function: Rc<Function>) -> Result<Value> {
+        self.trace = None;
+        Vm::new().run(self, function)
+    }
+
+    // Call a callable value, checking its arity against the arguments.
+    pub fn call(&mut self, callee: &Value, paren: &Token, arguments: &[Value]) -> Result<Value> {
+        let Some(function) = callee.as_callable() else {
+            return Err(Error::Runtime(
+                paren.clone(),
+                "Can only call functions and classes.".to_string(),
+            ));
+        };
+        if !function.arity().accepts(arguments.len()) {
+            return Err(Error::Runtime(
+                paren.clone(),
+                format!("Expected {} but got {}.", function.arity(), arguments.len()),
+            ));
+        }
+
+        gc::maybe_collect();
+
+        // A fresh call from outside Lox code starts a new traceback.
+        if self.frames.is_empty() {
+            self.trace = None;
+        }
+        let frame = match callee {
+            Value::LoxFunction(function) => Some(function.name().to_string()),
+            Value::Closure(closure) => Some(closure.name().to_string()),
+            Value::BoundMethod(method) => Some(method.name().to_string()),
+            Value::LoxClass(class) => class.find_method("init").map(|_| "init".to_string()),
+            _ => None,
+        };
+        let entered = frame.is_some();
+        if let Some(function) = frame {
+            // The script itself takes a frame on the virtual machine.
+            if self.frames.len() + 1 == FRAMES_MAX {
+                return Err(Error::Runtime(paren.clone(), "Stack overflow.".to_string()));
+            }
+            self.frames.push(Frame {
+                function,
+                call_site: paren.clone(),
+            });
+        }
+
+        let enclosing_call = self.call_site.replace(paren.clone());
+        // Each Lox call recurses on the native stack, so that is grown on the heap
+        // when running low, whatever the stack of the thread running the script.
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            function.call(self, arguments)
+        });
+        self.call_site = enclosing_call;
+        // Remember the frames the error was raised in before unwinding them.
+        if let Err(Error::Runtime(..) | Error::Throw(..)) = result {
+            if self.trace.is_none() {
+                self.trace = Some(self.frames.clone());
+            }
+        }
+        if entered {
+            self.frames.pop();
+        }
+        result
+    }
+
+    // Run a file into its own environment the first time it is imported.
+    pub(crate) fn import(&mut self, path: &Token) -> Result<Rc<LoxModule>> {
+        let Literal::String(relative) = &path.literal else {
+            unreachable!()
+        };
+        let error = |message: String| Error::Runtime(path.clone(), message);
+
+        let directory = match self.files.last().and_then(|f| f.parent()) {
+            Some(directory) => directory.to_path_buf(),
+            None => PathBuf::new(),
+        };
+        let file = fs::canonicalize(directory.join(relative))
+            .map_err(|e| error(format!("Can't open module '{}': {}.", relative, e)))?;
+
+        if let Some(i) = self.files.iter().position(|f| *f == file) {
+            let cycle: Vec<String> = self.files[i..]
+                .iter()
+                .chain([&file])
+                .map(|f| self.display_path(f))
+                .collect();
+            return Err(error(format!("Import cycle: {}.", cycle.join(" -> "))));
+        }
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(Rc::clone(module));
+        }
+
+        let source = fs::read_to_string(&file)
+            .map_err(|e| error(format!("Can't open module '{}': {}.", relative, e)))?;
+        self.files.push(file.clone());
+        let result = self.run_module(&source);
+        self.files.pop();
+
+        let environment = match result {
+            Ok(environment) => environment,
+            // A thrown value goes on to any `catch` around the import.
+            Err(e @ Error::Throw(..)) => return Err(e),
+            Err(e) => {
+                // The traceback of the module's own error doesn't apply to the import.
+                self.trace = None;
+                return Err(error(match e {
+                    Error::Runtime(token, message) => format!(
+                        "Error in module '{}' at line {}: {}",
+                        relative, token.line, message
+                    ),
+                    e => format!("Error in module '{}':\n{}", relative, e),
+                }));
+            }
+        };
+        let module = gc::alloc(LoxModule::new(relative, environment));
+        self.modules.insert(file, Rc::clone(&module));
+        Ok(module)
+    }
+
+    fn run_module(&mut self, source: &str) -> Result<Rc<RefCell<Environment>>> {
+        let stmts = lox::parse(source)?;
+
+        let environment = Environment::with_enclosing(Rc::clone(&self.builtins));
+        let environment = gc::alloc(RefCell::new(environment));
+        let globals = mem::replace(&mut self.globals, Rc::clone(&environment));
+        let enclosing = mem::replace(&mut self.environment, Rc::clone(&environment));
+        let result = match self.backend {
+            Backend::TreeWalker => self.run(&stmts),
+            Backend::Vm => Compiler::new()
+                .run(&stmts)
+                .and_then(|function| Vm::new().run(self, function))
+                .map(|_| ()),
+        };
+        self.globals = globals;
+        self.environment = enclosing;
+
+        result.map(|_| environment)
+    }
+
+    // Path of a file relative to the directory of the script, for messages.
+    fn display_path(&self, file: &Path) -> String {
+        let relative = match self.files.first().and_then(|f| f.parent()) {
+            Some(directory) => file.strip_prefix(directory).unwrap_or(file),
+            None => file,
+        };
+        relative.display().to_string()
+    }
+
+    // Token of the call being evaluated, for natives to report errors at.
+    pub fn call_site(&self) -> Token {
+        match &self.call_site {
+            Some(token) => token.clone(),
+            None => Token::new(TokenType::Eof, "", Literal::Nil, 0),
+        }
+    }
+
+    pub(crate) fn print(&mut self, value: &Value) -> Result<()> {
+        writeln!(self.output, "{value}")?;
+        Ok(())
+    }
+
+    // Runtime error pointing at the call being evaluated.
+    // Natives use it to reject their arguments.
+    pub fn error(&self, message: &str) -> Error {
+        Error::Runtime(self.call_site(), message.to_string())
+    }
+
+    // Where an uncaught runtime error happened, innermost call first:
+    //   [line 12] in fib()
+    //   [line 30] in script
+    // A frame repeated by recursion is listed once, followed by its count:
+    //   [line 2] in r()
+    //   ... 1020 more in r()
+    pub fn traceback(&self, error: &Error) -> Vec<String> {
+        let (Error::Runtime(token, _) | Error::Throw(token, _)) = error else {
+            return Vec::new();
+        };
+        let mut line = token.line;
+        let mut frames = Vec::new();
+        for frame in self.trace.iter().flatten().rev() {
+            frames.push((line, format!("{}()", frame.function)));
+            line = frame.call_site.line;
+        }
+        frames.push((line, "script".to_string()));
+
+        let mut traceback = Vec::new();
+        for run in frames.chunk_by(|a, b| a == b) {
+            let (line, place) = &run[0];
+            let frame = format!("[line {line}] in {place}");
+            if run.len() > 2 {
+                traceback.push(frame);
+                traceback.push(format!("... {} more in {place}", run.len() - 1));
+            } else {
+                traceback.extend(iter::repeat_n(frame, run.len()));
+            }
+        }
+        traceback
+    }
+
     fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
         match expr {
-            Expr::Assign(name, value) => {
+            Expr::Assign(name, value, depth) => {
                 let value = self.evaluate(value)?;
-                self.environment.borrow_mut().assign(name, value.clone())?;
+                match depth {
+                    Some(distance) => {
+                        self.environment
+                            .borrow()
+                            .assign_at(*distance, name, value.clone())?
+                    }
+                    None => self.globals.borrow().assign(name, value.clone())?,
+                }
                 Ok(value)
             }
             Expr::Binary(left, operator, right) => {
@@ -106,7 +445,9 @@ impl Interpreter {
                     },
                     TokenType::Plus => match (left, right) {
                         (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 + n2)),
-                        (Value::String(s1), Value::String(s2)) => Ok(Value::String(s1 + &s2)),
+                        (Value::String(s1), Value::String(s2)) => {
+                            Ok(Value::from([s1, s2].concat()))
+                        }
                         _ => Err(Error::Runtime(
                             operator.clone(),
                             "Operands must be two numbers or two strings.".to_string(),
@@ -137,27 +478,52 @@ impl Interpreter {
                     value_args.push(self.evaluate(argument)?);
                 }
 
-                match callee {
-                    Value::LoxFunction(f) => {
-                        if arguments.len() != f.arity() {
-                            return Err(Error::Runtime(
-                                paren.clone(),
-                                format!(
-                                    "Expected {} arguments but get {}.",
-                                    f.arity(),
-                                    arguments.len()
-                                ),
-                            ));
-                        }
-                        f.call(self, &value_args)
-                    }
+                self.call(&callee, &expr.spanning(paren), &value_args)
+            }
+            Expr::Function(_keyword, params, body) => Ok(Value::LoxFunction(LoxFunction::new(
+                None,
+                params,
+                body,
+                Rc::clone(&self.environment),
+                Rc::clone(&self.globals),
+                false,
+            ))),
+            Expr::Get(object, name) => match self.evaluate(object)? {
+                Value::LoxInstance(instance) => LoxInstance::get(&instance, name),
+                Value::Exception(exception) => exception.get(name),
+                Value::Module(module) => module.get(name),
+                _ => Err(Error::Runtime(
+                    name.clone(),
+                    "Only instances have properties.".to_string(),
+                )),
+            },
+            Expr::Grouping(expr) => self.evaluate(expr),
+            Expr::Index(object, bracket, index) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                match object {
+                    Value::List(list) => list.get(bracket, &index),
+                    Value::Map(map) => map.get(bracket, &index),
                     _ => Err(Error::Runtime(
-                        paren.clone(),
-                        "Can only call functions and classes.".to_string(),
+                        bracket.clone(),
+                        "Only lists and maps can be indexed.".to_string(),
                     )),
                 }
             }
-            Expr::Grouping(expr) => self.evaluate(expr),
+            Expr::Interpolation(_start, parts) => {
+                let mut s = String::new();
+                for part in parts {
+                    s.push_str(&self.evaluate(part)?.to_string());
+                }
+                Ok(Value::from(s))
+            }
+            Expr::List(_bracket, elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.evaluate(element)?);
+                }
+                Ok(Value::from(values))
+            }
             Expr::Literal(value) => Ok(value.clone()),
             Expr::Logical(left, operator, right) => {
                 let left = self.evaluate(left)?;
@@ -170,6 +536,67 @@ impl Interpreter {
                 }
                 self.evaluate(right)
             }
+            Expr::Map(brace, entries) => {
+                let mut values = Vec::new();
+                for (key, value) in entries {
+                    values.push((self.evaluate(key)?, self.evaluate(value)?));
+                }
+                Ok(Value::Map(gc::alloc(LoxMap::new(brace, values)?)))
+            }
+            Expr::Set(object, name, value) => match self.evaluate(object)? {
+                Value::LoxInstance(instance) => {
+                    let value = self.evaluate(value)?;
+                    instance.set(&name.lexeme, value.clone());
+                    Ok(value)
+                }
+                _ => Err(Error::Runtime(
+                    name.clone(),
+                    "Only instances have fields.".to_string(),
+                )),
+            },
+            Expr::SetIndex(object, bracket, index, value) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+                match object {
+                    Value::List(list) => {
+                        list.set(bracket, &index, value.clone())?;
+                        Ok(value)
+                    }
+                    Value::Map(map) => {
+                        map.set(bracket, &index, value.clone())?;
+                        Ok(value)
+                    }
+                    _ => Err(Error::Runtime(
+                        bracket.clone(),
+                        "Only lists and maps can be indexed.".to_string(),
+                    )),
+                }
+            }
+            Expr::Super(keyword, method, depth) => {
+                let distance = depth.expect("Failed to resolve 'super'.");
+                let superclass = self.environment.borrow().get_at(distance, keyword)?;
+                // `this` is always bound one environment inside `super`.
+                let this = Token::new(TokenType::This, "this", Literal::Nil, keyword.line);
+                let object = self.environment.borrow().get_at(distance - 1, &this)?;
+
+                match (superclass, object) {
+                    (Value::LoxClass(class), Value::LoxInstance(instance)) => {
+                        match class.bind_method(&method.lexeme, &instance)? {
+                            Some(method) => Ok(method),
+                            None => Err(Error::Runtime(
+                                method.clone(),
+                                format!("Undefined property '{}'.", method.lexeme),
+                            )),
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Expr::This(keyword, depth) => match depth {
+                Some(distance) => self.environment.borrow().get_at(*distance, keyword),
+                None => self.globals.borrow().get(keyword),
+            },
             Expr::Unary(operator, right) => {
                 let right = self.evaluate(right)?;
                 match operator.token_type {
@@ -184,52 +611,162 @@ impl Interpreter {
                     _ => unreachable!(),
                 }
             }
-            Expr::Variable(token) => self.environment.borrow().get(token),
+            Expr::Variable(token, depth) => match depth {
+                Some(distance) => self.environment.borrow().get_at(*distance, token),
+                None => self.globals.borrow().get(token),
+            },
         }
     }
 
-    pub fn execute(&mut self, stmt: &Stmt) -> Result<()> {
+    // Run a `catch` block in a scope binding the caught exception.
+    fn execute_catch(
+        &mut self,
+        name: &Token,
+        exception: Value,
+        handler: &Stmt,
+    ) -> Result<ControlFlow> {
+        let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+        environment.define(&name.lexeme, exception)?;
+
+        let previous = mem::replace(&mut self.environment, gc::alloc(RefCell::new(environment)));
+        let result = self.execute(handler);
+        self.environment = previous;
+        result
+    }
+
+    pub(crate) fn execute(&mut self, stmt: &Stmt) -> Result<ControlFlow> {
         match stmt {
             Stmt::Block(stmts) => {
                 let previous = Rc::clone(&self.environment);
-                self.environment = Rc::new(RefCell::new(Environment::with_enclosing(Rc::clone(
+                self.environment = gc::alloc(RefCell::new(Environment::with_enclosing(Rc::clone(
                     &self.environment,
                 ))));
 
                 for stmt in stmts {
-                    if let Err(e) = self.execute(stmt) {
-                        self.environment = previous;
-                        return Err(e);
+                    match self.execute(stmt) {
+                        Ok(ControlFlow::Next) => (),
+                        result => {
+                            self.environment = previous;
+                            return result;
+                        }
                     }
                 }
 
                 self.environment = previous;
             }
+            Stmt::Break(_keyword) => return Ok(ControlFlow::Break),
+            Stmt::Class(name, superclass, stmts) => {
+                let superclass = match superclass {
+                    Some(expr) => match self.evaluate(expr)? {
+                        Value::LoxClass(class) => Some(class),
+                        _ => {
+                            let token = match expr {
+                                Expr::Variable(token, _) => token.clone(),
+                                _ => name.clone(),
+                            };
+                            return Err(Error::Runtime(
+                                token,
+                                "Superclass must be a class.".to_string(),
+                            ));
+                        }
+                    },
+                    None => None,
+                };
+
+                self.environment
+                    .borrow_mut()
+                    .define(&name.lexeme, Value::Nil)?;
+
+                // Methods of a subclass close over an environment that binds `super`.
+                let previous = Rc::clone(&self.environment);
+                if let Some(ref s) = superclass {
+                    let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                    environment.define("super", Value::LoxClass(Rc::clone(s)))?;
+                    self.environment = gc::alloc(RefCell::new(environment));
+                }
+
+                let mut methods = HashMap::new();
+                for stmt in stmts {
+                    if let Stmt::Function(method, params, body) = stmt {
+                        let function = LoxFunction::new(
+                            Some(method),
+                            params,
+                            body,
+                            Rc::clone(&self.environment),
+                            Rc::clone(&self.globals),
+                            method.lexeme == "init",
+                        );
+                        methods.insert(method.lexeme.clone(), Value::LoxFunction(function));
+                    }
+                }
+                self.environment = previous;
+
+                let class = LoxClass::new(&name.lexeme, superclass, methods);
+                self.environment
+                    .borrow_mut()
+                    .assign(name, Value::LoxClass(gc::alloc(class)))?;
+            }
+            Stmt::Continue(_keyword) => return Ok(ControlFlow::Continue),
             Stmt::Expression(expr) => {
                 self.evaluate(expr)?;
             }
             Stmt::Function(name, params, body) => {
-                let function = LoxFunction::new(name, params, body, Rc::clone(&self.environment));
+                let function = LoxFunction::new(
+                    Some(name),
+                    params,
+                    body,
+                    Rc::clone(&self.environment),
+                    Rc::clone(&self.globals),
+                    false,
+                );
                 self.environment
                     .borrow_mut()
                     .define(&name.lexeme, Value::LoxFunction(function))?;
             }
+            Stmt::Import(_keyword, name, path) => {
+                let module = self.import(path)?;
+                self.environment
+                    .borrow_mut()
+                    .define(&name.lexeme, Value::Module(module))?;
+            }
             Stmt::If(condition, then_branch, else_branch) => {
                 if self.evaluate(condition)?.is_truthy() {
-                    self.execute(then_branch)?;
+                    return self.execute(then_branch);
                 } else if let Some(b) = else_branch {
-                    self.execute(b)?;
+                    return self.execute(b);
                 }
             }
-            Stmt::Print(expr) => {
-                println!("{}", self.evaluate(expr)?);
+            Stmt::Print(_keyword, expr) => {
+                let value = self.evaluate(expr)?;
+                self.print(&value)?;
             }
             Stmt::Return(_keyword, value) => {
                 let value = match value {
                     Some(v) => self.evaluate(v)?,
                     None => Value::Nil,
                 };
-                return Err(Error::Return(value));
+                return Ok(ControlFlow::Return(value));
+            }
+            Stmt::Throw(keyword, value) => {
+                let value = self.evaluate(value)?;
+                return Err(Error::Throw(keyword.clone(), Box::new(value)));
+            }
+            Stmt::Try(body, handler, finally) => {
+                let mut result = self.execute(body);
+                if let Some((name, handler)) = handler {
+                    if let Some(exception) = result.as_ref().err().and_then(caught) {
+                        self.trace = None;
+                        result = self.execute_catch(name, exception, handler);
+                    }
+                }
+                // A `finally` clause that jumps or fails replaces the outcome of the others.
+                if let Some(f) = finally {
+                    match self.execute(f)? {
+                        ControlFlow::Next => (),
+                        flow => return Ok(flow),
+                    }
+                }
+                return result;
             }
             Stmt::Var(token, expr) => {
                 let value = match expr {
@@ -238,12 +775,31 @@ impl Interpreter {
                 };
                 self.environment.borrow_mut().define(&token.lexeme, value)?;
             }
-            Stmt::While(condition, body) => {
+            Stmt::While(condition, body, increment) => {
                 while self.evaluate(condition)?.is_truthy() {
-                    self.execute(body)?;
+                    gc::maybe_collect();
+                    match self.execute(body)? {
+                        ControlFlow::Break => break,
+                        ControlFlow::Next | ControlFlow::Continue => (),
+                        flow => return Ok(flow),
+                    }
+                    if let Some(i) = increment {
+                        self.evaluate(i)?;
+                    }
                 }
             }
         }
-        Ok(())
+        Ok(ControlFlow::Next)
+    }
+}
+
+// Value bound by a `catch` clause, if the error can be caught at all.
+pub(crate) fn caught(error: &Error) -> Option<Value> {
+    match error {
+        Error::Runtime(token, message) => Some(Value::Exception(gc::alloc(LoxException::new(
+            message, token.line,
+        )))),
+        Error::Throw(_, value) => Some(*value.clone()),
+        _ => None,
     }
 }
@@ -1,14 +1,27 @@
-mod callable;
+mod bytecode;
+pub mod callable;
+mod chunk;
+mod class;
+mod compiler;
+mod disassembler;
 mod environment;
+mod exception;
 mod expr;
 mod function;
-mod interpreter;
+pub mod gc;
+mod interner;
+pub mod interpreter;
+mod list;
 mod literal;
 pub mod lox;
+mod map;
+mod module;
 mod parser;
+mod resolver;
 pub mod result;
 mod scanner;
 mod stmt;
 mod token;
 mod token_type;
-mod value;
+pub mod value;
+mod vm;
@@ -0,0 +1,194 @@
+use crate::gc;
+use crate::gc::Trace;
+use crate::gc::Tracer;
+use crate::interpreter::Interpreter;
+use crate::result::Error;
+use crate::result::Result;
+use crate::token::Token;
+use crate::value;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+
+// Growable array, shared by every value that refers to it
+#[derive(Debug, Default)]
+pub struct LoxList {
+    elements: RefCell<Vec<Value>>,
+}
+
+impl LoxList {
+    pub fn new(elements: Vec<Value>) -> Self {
+        Self {
+            elements: RefCell::new(elements),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.borrow().is_empty()
+    }
+
+    pub fn get(&self, bracket: &Token, index: &Value) -> Result<Value> {
+        let i = self.index(bracket, index)?;
+        Ok(self.elements.borrow()[i].clone())
+    }
+
+    pub fn set(&self, bracket: &Token, index: &Value, value: Value) -> Result<()> {
+        let i = self.index(bracket, index)?;
+        self.elements.borrow_mut()[i] = value;
+        Ok(())
+    }
+
+    pub fn push(&self, value: Value) {
+        self.elements.borrow_mut().push(value);
+    }
+
+    pub fn pop(&self) -> Option<Value> {
+        self.elements.borrow_mut().pop()
+    }
+
+    // Copy of the elements from `start` up to but not including `end`.
+    pub fn slice(&self, start: usize, end: usize) -> Option<Self> {
+        let elements = self.elements.borrow().get(start..end)?.to_vec();
+        Some(Self::new(elements))
+    }
+
+    // Position of an element, which must be a whole number within bounds.
+    fn index(&self, bracket: &Token, index: &Value) -> Result<usize> {
+        let n = match index {
+            Value::Number(n) if n.fract() == 0.0 => *n,
+            _ => {
+                return Err(Error::Runtime(
+                    bracket.clone(),
+                    "List index must be a whole number.".to_string(),
+                ))
+            }
+        };
+        if n < 0.0 || n >= self.len() as f64 {
+            return Err(Error::Runtime(
+                bracket.clone(),
+                format!("List index {} out of bounds for length {}.", n, self.len()),
+            ));
+        }
+        Ok(n as usize)
+    }
+}
+
+impl fmt::Display for LoxList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        value::display_nested(self, f, "[...]", |f| {
+            let elements: Vec<String> = self
+                .elements
+                .borrow()
+                .iter()
+                .map(|e| e.to_string())
+                .collect();
+            write!(f, "[{}]", elements.join(", "))
+        })
+    }
+}
+
+// Lists are only equal to themselves
+impl cmp::PartialEq for LoxList {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for LoxList {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+impl Trace for LoxList {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(elements) = self.elements.try_borrow() {
+            elements.iter().for_each(|e| tracer.mark_value(e));
+        }
+    }
+
+    fn clear(&self) {
+        let elements = self
+            .elements
+            .try_borrow_mut()
+            .map(|mut e| mem::take(&mut *e));
+        drop(elements);
+    }
+
+    fn size(&self) -> usize {
+        let capacity = self.elements.try_borrow().map_or(0, |e| e.capacity());
+        mem::size_of::<Self>() + capacity * mem::size_of::<Value>()
+    }
+}
+
+// Register the primitive functions working on lists.
+pub fn define_natives(interpreter: &mut Interpreter) -> Result<()> {
+    interpreter.define_native("len", 1, len)?;
+    interpreter.define_native("push", 2, push)?;
+    interpreter.define_native("pop", 1, pop)?;
+    interpreter.define_native("slice", 3, slice)?;
+    Ok(())
+}
+
+// len(list), len(map) or len(string)
+fn len(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        Value::List(list) => Ok(Value::Number(list.len() as f64)),
+        Value::Map(map) => Ok(Value::Number(map.len() as f64)),
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        _ => Err(interpreter.error("Argument must be a list, a map or a string.")),
+    }
+}
+
+// push(list, value) appends the value to the list.
+fn push(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let list = as_list(interpreter, &arguments[0])?;
+    list.push(arguments[1].clone());
+    Ok(Value::Nil)
+}
+
+// pop(list) removes the last element and returns it.
+fn pop(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let list = as_list(interpreter, &arguments[0])?;
+    list.pop()
+        .ok_or_else(|| interpreter.error("Can't pop from an empty list."))
+}
+
+// slice(list, start, end) returns a new list with the elements in [start, end).
+fn slice(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let list = as_list(interpreter, &arguments[0])?;
+    let bounds = match (&arguments[1], &arguments[2]) {
+        (Value::Number(start), Value::Number(end))
+            if start.fract() == 0.0 && end.fract() == 0.0 =>
+        {
+            if *start < 0.0 || *end < 0.0 {
+                None
+            } else {
+                list.slice(*start as usize, *end as usize)
+            }
+        }
+        _ => return Err(interpreter.error("Slice bounds must be whole numbers.")),
+    };
+    match bounds {
+        Some(slice) => Ok(Value::List(gc::alloc(slice))),
+        None => Err(interpreter.error(&format!(
+            "Slice bounds out of range for length {}.",
+            list.len()
+        ))),
+    }
+}
+
+fn as_list(interpreter: &Interpreter, value: &Value) -> Result<Rc<LoxList>> {
+    match value {
+        Value::List(list) => Ok(Rc::clone(list)),
+        _ => Err(interpreter.error("Argument must be a list.")),
+    }
+}
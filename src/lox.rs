@@ -1,31 +1,134 @@
+use crate::bytecode;
+use crate::callable::Arity;
+use crate::compiler::Compiler;
+use crate::disassembler;
+use crate::gc;
+use crate::interpreter::Backend;
 use crate::interpreter::Interpreter;
+use crate::literal::Literal;
 use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::result::Error;
 use crate::result::Result;
 use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use crate::token_type::TokenType;
+use crate::value::Value;
 use std::fs;
 use std::io::BufRead;
 use std::io::Write;
 use std::io::{self};
+use std::path::Path;
 
 pub struct Lox {
     interpreter: Interpreter,
+    // Destination of error reports
+    diagnostics: Box<dyn Write>,
+    // Last evaluated source code, for quoting it in error reports
+    source: String,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Lox {
-    fn new() -> Self {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()), Box::new(io::stderr()))
+    }
+
+    // Send `print` output and diagnostics to the given writers instead of stdout/stderr.
+    pub fn with_output(output: Box<dyn Write>, diagnostics: Box<dyn Write>) -> Self {
         Self {
-            interpreter: Interpreter::new(),
+            interpreter: Interpreter::with_output(output),
+            diagnostics,
+            source: String::new(),
+        }
+    }
+
+    // Make a Rust closure callable from Lox code under `name`.
+    // The closure may capture host state, e.g. a database handle.
+    pub fn register_native<F>(
+        &mut self,
+        name: &str,
+        arity: impl Into<Arity>,
+        function: F,
+    ) -> Result<()>
+    where
+        F: Fn(&mut Interpreter, &[Value]) -> Result<Value> + 'static,
+    {
+        self.interpreter.define_native(name, arity, function)
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.interpreter.set_backend(backend);
+    }
+
+    // Run a script, reporting any error to stderr before returning it.
+    pub fn run_file(path: &str, backend: Backend) -> Result<()> {
+        let mut lox = Self::new();
+        lox.set_backend(backend);
+        lox.interpreter.set_script_path(Path::new(path));
+        let result = fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|src| lox.run(&src));
+        if let Err(ref e) = result {
+            lox.report(e)?;
         }
+        result
     }
 
-    pub fn run_file(path: &str) -> Result<()> {
+    // Print the bytecode of a script without running it.
+    pub fn disassemble_file(path: &str) -> Result<()> {
+        Self::new().write_disassembly(path)
+    }
+
+    // Write the bytecode of a script to the output writer, like `print` does.
+    pub fn write_disassembly(&mut self, path: &str) -> Result<()> {
+        let result = fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|src| self.disassemble(&src))
+            .and_then(|listing| {
+                write!(self.interpreter.output, "{}", listing).map_err(Error::from)
+            });
+        if let Err(ref e) = result {
+            self.report(e)?;
+        }
+        result
+    }
+
+    // Compile a script and write its bytecode to `output`.
+    pub fn compile_file(path: &str, output: &str) -> Result<()> {
         let mut lox = Self::new();
-        let src = fs::read_to_string(path)?;
-        lox.run(&src)
+        let result = fs::read_to_string(path)
+            .map_err(Error::from)
+            .and_then(|src| lox.compile(&src))
+            .and_then(|bytes| fs::write(output, bytes).map_err(Error::from));
+        if let Err(ref e) = result {
+            lox.report(e)?;
+        }
+        result
     }
 
-    pub fn run_prompt() -> Result<()> {
+    // Run bytecode written by `compile_file` on the virtual machine.
+    pub fn load_file(path: &str) -> Result<()> {
         let mut lox = Self::new();
+        lox.interpreter.set_script_path(Path::new(path));
+        let result = fs::read(path)
+            .map_err(Error::from)
+            .and_then(|bytes| lox.run_bytecode(&bytes));
+        if let Err(ref e) = result {
+            lox.report(e)?;
+        }
+        result
+    }
+
+    pub fn run_prompt(backend: Backend) -> Result<()> {
+        let mut lox = Self::new();
+        lox.set_backend(backend);
         let mut handle = io::stdin().lock();
         let mut buffer = String::new();
 
@@ -37,19 +140,383 @@ impl Lox {
                 break;
             }
             if let Err(e) = lox.run(&buffer) {
-                eprintln!("{e}"); // Reset an error
+                lox.report(&e)?; // Reset an error
             }
+            gc::maybe_collect();
             buffer.clear();
         }
 
         Ok(())
     }
 
-    fn run(&mut self, source: &str) -> Result<()> {
-        let tokens = Scanner::new(source).run()?;
-        let stmts = Parser::new(tokens).run()?;
-        self.interpreter.run(&stmts)?;
+    // Write an error to the diagnostics writer, quoting the code it points at.
+    pub fn report(&mut self, error: &Error) -> Result<()> {
+        let message = match error {
+            Error::Runtime(_, message) => Some(message.clone()),
+            Error::Throw(_, value) => Some(value.to_string()),
+            _ => None,
+        };
+        let report = match message {
+            // The traceback takes the place of the single `[line N]`.
+            Some(message) => {
+                let mut traceback = self.interpreter.traceback(error).into_iter();
+                let mut lines = vec![message];
+                lines.extend(traceback.next());
+                lines.extend(error.snippet(&self.source));
+                lines.extend(traceback);
+                lines.join("\n")
+            }
+            None => error.render(&self.source),
+        };
+        writeln!(self.diagnostics, "{}", report)?;
+        Ok(())
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<()> {
+        self.eval(source)?;
+        Ok(())
+    }
+
+    // Run source code and return the value of its last expression statement,
+    // or nil if the last statement is not an expression.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        self.source = source.to_string();
+        let stmts = parse(source)?;
+        self.interpreter.eval(&stmts)
+    }
+
+    // Compile source code to bytecode and list its instructions.
+    pub fn disassemble(&mut self, source: &str) -> Result<String> {
+        self.source = source.to_string();
+        let stmts = parse(source)?;
+        let function = Compiler::new().run(&stmts)?;
+        Ok(disassembler::disassemble(&function, "script"))
+    }
 
+    // Compile source code to the bytecode file format.
+    pub fn compile(&mut self, source: &str) -> Result<Vec<u8>> {
+        self.source = source.to_string();
+        let stmts = parse(source)?;
+        let function = Compiler::new().run(&stmts)?;
+        Ok(bytecode::write(&function))
+    }
+
+    // Run a compiled script. Its source is not available, so errors are reported
+    // without quoting it. Imported modules are compiled for the virtual machine.
+    pub fn run_bytecode(&mut self, bytes: &[u8]) -> Result<()> {
+        self.source.clear();
+        let function = bytecode::read(bytes)?;
+        self.set_backend(Backend::Vm);
+        self.interpreter.run_compiled(function)?;
         Ok(())
     }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let token = Token::new(TokenType::Identifier, name, Literal::Nil, 0);
+        self.interpreter.globals.borrow().get(&token).ok()
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) -> Result<()> {
+        self.interpreter
+            .globals
+            .borrow_mut()
+            .define(name, value.into())
+    }
+
+    // Call a global function or class by name with Rust arguments.
+    pub fn call(&mut self, name: &str, arguments: &[Value]) -> Result<Value> {
+        let token = Token::new(TokenType::Identifier, name, Literal::Nil, 0);
+        let callee = self.interpreter.globals.borrow().get(&token)?;
+        self.interpreter.call(&callee, &token, arguments)
+    }
+}
+
+// Scan, parse and resolve a script, collecting every static error.
+pub(crate) fn parse(source: &str) -> Result<Vec<Stmt>> {
+    // Parse even if scanning failed, to report syntax errors as well.
+    let (tokens, mut errors) = Scanner::new(source).run();
+    let parsed = Parser::new(tokens).run();
+    let mut stmts = match parsed {
+        Ok(stmts) if errors.is_empty() => stmts,
+        Ok(_) => return Err(Error::Multiple(errors)),
+        Err(Error::Multiple(parse_errors)) => {
+            errors.extend(parse_errors);
+            return Err(Error::Multiple(errors));
+        }
+        Err(e) => return Err(e),
+    };
+    Resolver::new().run(&mut stmts)?;
+    Ok(stmts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn call_native() {
+        fn double(_: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+            match arguments[0] {
+                Value::Number(n) => Ok(Value::Number(n * 2.0)),
+                _ => Ok(Value::Nil),
+            }
+        }
+
+        let mut lox = Lox::new();
+        lox.register_native("double", 1, double).unwrap();
+        assert!(lox.run("var a = double(21);").is_ok());
+        assert!(lox.run("double();").is_err());
+        assert!(lox.run("clock();").is_ok());
+    }
+
+    #[test]
+    fn eval_last_expression() {
+        let mut lox = Lox::new();
+        assert_eq!(lox.eval("1 + 2;").unwrap(), Value::Number(3.0));
+        assert_eq!(lox.eval("var a = 1;").unwrap(), Value::Nil);
+        assert_eq!(lox.eval("a = a + 1; a * 10;").unwrap(), Value::Number(20.0));
+    }
+
+    #[test]
+    fn read_and_write_globals() {
+        let mut lox = Lox::new();
+        lox.set_global("name", "lox").unwrap();
+        assert_eq!(lox.eval("name + \"!\";").unwrap(), Value::from("lox!"));
+        lox.run("var answer = 42;").unwrap();
+        assert_eq!(lox.get_global("answer"), Some(Value::Number(42.0)));
+        assert_eq!(lox.get_global("undefined"), None);
+    }
+
+    #[test]
+    fn call_function_by_name() {
+        let mut lox = Lox::new();
+        lox.run("fun add(a, b) { return a + b; }").unwrap();
+        assert_eq!(
+            lox.call("add", &[1.0.into(), 2.0.into()]).unwrap(),
+            Value::Number(3.0)
+        );
+        assert!(lox.call("add", &[1.0.into()]).is_err());
+        assert!(lox.call("undefined", &[]).is_err());
+    }
+
+    // Writer whose contents can still be read after it is handed to `Lox`.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn capture_output() {
+        let output = SharedBuffer::default();
+        let diagnostics = SharedBuffer::default();
+        let mut lox = Lox::with_output(Box::new(output.clone()), Box::new(diagnostics.clone()));
+
+        lox.run("print 1 + 2; print \"done\";").unwrap();
+        assert_eq!(output.contents(), "3\ndone\n");
+
+        let error = lox.run("print -nil;").unwrap_err();
+        lox.report(&error).unwrap();
+        assert_eq!(
+            diagnostics.contents(),
+            "Operand must be a number.\n[line 1] in script\n1 | print -nil;\n  |       ^\n"
+        );
+    }
+
+    #[test]
+    fn report_traceback() {
+        let diagnostics = SharedBuffer::default();
+        let mut lox = Lox::with_output(Box::new(io::sink()), Box::new(diagnostics.clone()));
+
+        let source = "fun inner() {\n  return -nil;\n}\nfun outer() {\n  inner();\n}\nouter();";
+        let error = lox.run(source).unwrap_err();
+        lox.report(&error).unwrap();
+        assert_eq!(
+            diagnostics.contents(),
+            "Operand must be a number.\n\
+             [line 2] in inner()\n\
+             2 |   return -nil;\n  |          ^\n\
+             [line 5] in outer()\n\
+             [line 7] in script\n"
+        );
+    }
+
+    #[test]
+    fn report_recursive_traceback() {
+        let diagnostics = SharedBuffer::default();
+        let mut lox = Lox::with_output(Box::new(io::sink()), Box::new(diagnostics.clone()));
+
+        let source = "fun r(n) {\n  if (n == 0) return -nil;\n  r(n - 1);\n}\nr(5);";
+        let error = lox.run(source).unwrap_err();
+        lox.report(&error).unwrap();
+        assert_eq!(
+            diagnostics.contents(),
+            "Operand must be a number.\n\
+             [line 2] in r()\n\
+             2 |   if (n == 0) return -nil;\n  |                      ^\n\
+             [line 3] in r()\n\
+             ... 4 more in r()\n\
+             [line 5] in script\n"
+        );
+    }
+
+    #[test]
+    fn report_error_from_earlier_source() {
+        let diagnostics = SharedBuffer::default();
+        let mut lox = Lox::with_output(Box::new(io::sink()), Box::new(diagnostics.clone()));
+
+        // The call spans bytes of the first line, which fall inside `é` here.
+        lox.run("fun h() {} fun g() { return h(1, 2); }").unwrap();
+        let error = lox
+            .run("print \"aaaaaaaaaaaaaaaaaaaaé\";g( );")
+            .unwrap_err();
+        lox.report(&error).unwrap();
+        assert!(diagnostics
+            .contents()
+            .starts_with("Expected 0 arguments but got 2.\n"));
+    }
+
+    #[test]
+    fn report_error_from_module() {
+        let diagnostics = SharedBuffer::default();
+        let mut lox = Lox::with_output(Box::new(io::sink()), Box::new(diagnostics.clone()));
+        lox.interpreter
+            .set_script_path(Path::new("examples/import/main.lox"));
+
+        // The `*` failing in the module is at the same offset as the one in the
+        // comment, which must not be quoted in its place.
+        let source = format!(
+            "import \"modules/math.lox\";\n// {}*\nprint math.square(nil);",
+            "-".repeat(36)
+        );
+        let error = lox.run(&source).unwrap_err();
+        lox.report(&error).unwrap();
+        assert_eq!(
+            diagnostics.contents(),
+            "Operands must be numbers.\n[line 6] in square()\n[line 3] in script\n"
+        );
+    }
+
+    #[test]
+    fn capture_disassembly() {
+        let output = SharedBuffer::default();
+        let mut lox = Lox::with_output(Box::new(output.clone()), Box::new(io::sink()));
+        lox.write_disassembly("examples/hello.lox").unwrap();
+        assert!(output.contents().starts_with("== script ==\n"));
+    }
+
+    #[test]
+    fn run_on_vm() {
+        let output = SharedBuffer::default();
+        let mut lox = Lox::with_output(Box::new(output.clone()), Box::new(io::sink()));
+        lox.set_backend(Backend::Vm);
+
+        lox.run("fun add(a, b) { return a + b; } print add(1, 2);")
+            .unwrap();
+        assert_eq!(output.contents(), "3\n");
+        assert_eq!(lox.eval("add(2, 3) * 10;").unwrap(), Value::Number(50.0));
+        assert_eq!(
+            lox.call("add", &[1.0.into(), 2.0.into()]).unwrap(),
+            Value::Number(3.0)
+        );
+        assert!(lox.call("add", &[1.0.into()]).is_err());
+    }
+
+    #[test]
+    fn construct_class_declared_before_switching_to_vm() {
+        let mut lox = Lox::new();
+        lox.run("class Point { init(x) { this.x = x; } }").unwrap();
+        lox.set_backend(Backend::Vm);
+
+        assert_eq!(lox.eval("Point(3).x;").unwrap(), Value::Number(3.0));
+        match lox.run("Point();") {
+            Err(Error::Runtime(_, message)) => {
+                assert_eq!(message, "Expected 1 argument but got 0.")
+            }
+            result => panic!("Expected a runtime error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn recurse_on_default_thread() {
+        // Spawned threads get a smaller stack than the main thread.
+        let result = std::thread::spawn(|| {
+            let mut lox = Lox::new();
+            lox.run("fun depth(n) { if (n == 0) return 0; return 1 + depth(n - 1); }")
+                .unwrap();
+            // Values are tied to the thread, so only their text is sent back.
+            let mut eval = |source| match lox.eval(source) {
+                Ok(value) => value.to_string(),
+                Err(e) => e.to_string(),
+            };
+            (eval("depth(1000);"), eval("depth(2000);"))
+        })
+        .join()
+        .unwrap();
+        assert_eq!(result.0, "1000");
+        assert_eq!(result.1, "Stack overflow.\n[line 1]");
+    }
+
+    #[test]
+    fn share_list_with_script() {
+        let mut lox = Lox::new();
+        let list = Value::from(vec![1.0.into(), 2.0.into()]);
+        lox.set_global("list", list.clone()).unwrap();
+        lox.run("push(list, 3); list[0] = \"one\";").unwrap();
+        assert_eq!(list.to_string(), "[one, 2, 3]");
+        assert_eq!(lox.eval("len(list);").unwrap(), Value::Number(3.0));
+    }
+
+    #[test]
+    fn call_native_closure() {
+        use std::cell::Cell;
+
+        let count = Rc::new(Cell::new(0));
+        let captured = Rc::clone(&count);
+
+        let mut lox = Lox::new();
+        lox.register_native("tick", 0, move |_, _| {
+            captured.set(captured.get() + 1);
+            Ok(Value::Nil)
+        })
+        .unwrap();
+        assert!(lox.run("tick(); tick();").is_ok());
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn call_variadic_native() {
+        let mut lox = Lox::new();
+        lox.register_native("count", Arity::Any, |_, arguments| {
+            Ok(Value::Number(arguments.len() as f64))
+        })
+        .unwrap();
+        lox.register_native("first", Arity::AtLeast(1), |_, arguments| {
+            Ok(arguments[0].clone())
+        })
+        .unwrap();
+        assert!(lox.run("count(); count(1, 2, 3);").is_ok());
+        assert!(lox.run("first(1); first(1, 2);").is_ok());
+        match lox.run("first();") {
+            Err(Error::Runtime(_, message)) => {
+                assert_eq!(message, "Expected at least 1 argument but got 0.")
+            }
+            result => panic!("Expected a runtime error, got {:?}", result),
+        }
+    }
 }
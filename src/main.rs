@@ -1,5 +1,6 @@
 extern crate rslox;
 
+use rslox::interpreter::Backend;
 use rslox::lox::Lox;
 use rslox::result::Error;
 use rslox::result::Result;
@@ -11,18 +12,25 @@ fn main() -> Result<()> {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    let args: Vec<String> = env::args().skip(1).collect();
-    let result = match args.len() {
-        0 => Lox::run_prompt(),
-        1 => Lox::run_file(&args[0]),
-        _ => Err(Error::Usage),
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|a| a.starts_with("--"));
+    let result = match (flags.as_slice(), args.as_slice()) {
+        ([], []) => Lox::run_prompt(Backend::TreeWalker),
+        ([], [path]) => Lox::run_file(path, Backend::TreeWalker),
+        ([flag], []) if flag == "--vm" => Lox::run_prompt(Backend::Vm),
+        ([flag], [path]) if flag == "--vm" => Lox::run_file(path, Backend::Vm),
+        ([flag], [path]) if flag == "--disassemble" => Lox::disassemble_file(path),
+        ([flag], [path, output]) if flag == "--compile" => Lox::compile_file(path, output),
+        ([flag], [path]) if flag == "--load" => Lox::load_file(path),
+        _ => {
+            eprintln!("{}", Error::Usage);
+            Err(Error::Usage)
+        }
     };
 
+    // Errors from running code have already been reported by `Lox`.
     match result {
         Ok(_) => exit(0),
-        Err(e) => {
-            eprintln!("{e}");
-            exit(1)
-        }
+        Err(e) => exit(e.exit_code()),
     }
 }
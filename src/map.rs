@@ -0,0 +1,216 @@
+use crate::gc::Trace;
+use crate::gc::Tracer;
+use crate::interpreter::Interpreter;
+use crate::result::Error;
+use crate::result::Result;
+use crate::token::Token;
+use crate::value;
+use crate::value::Value;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+
+// Value usable as a map key, hashed by content.
+// Numbers are stored as bits so that they can be hashed. NaN is refused because
+// it is never equal to itself, so an entry keyed by it could never be found.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Boolean(bool),
+    Number(u64),
+    String(Rc<str>),
+    Nil,
+}
+
+impl Key {
+    fn new(token: &Token, value: &Value) -> Result<Self> {
+        match value {
+            Value::Boolean(b) => Ok(Self::Boolean(*b)),
+            Value::Number(n) if n.is_nan() => Err(Error::Runtime(
+                token.clone(),
+                "Map key can't be NaN.".to_string(),
+            )),
+            // 0 and -0 are equal, so they must name the same entry.
+            Value::Number(n) if *n == 0.0 => Ok(Self::Number(0f64.to_bits())),
+            Value::Number(n) => Ok(Self::Number(n.to_bits())),
+            Value::String(s) => Ok(Self::String(s.clone())),
+            Value::Nil => Ok(Self::Nil),
+            _ => Err(Error::Runtime(
+                token.clone(),
+                "Map key must be a string, number, boolean or nil.".to_string(),
+            )),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Boolean(b) => Value::Boolean(*b),
+            Self::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            Self::String(s) => Value::String(s.clone()),
+            Self::Nil => Value::Nil,
+        }
+    }
+}
+
+// Strings are quoted, so that the key "1" can be told from the key 1.
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(s) => write!(f, "{:?}", s),
+            key => write!(f, "{}", key.to_value()),
+        }
+    }
+}
+
+// Dictionary that remembers the order its keys were inserted in,
+// shared by every value that refers to it
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: RefCell<IndexMap<Key, Value>>,
+}
+
+impl LoxMap {
+    // Build a map from key-value pairs, later duplicates replacing earlier ones.
+    pub fn new(token: &Token, entries: Vec<(Value, Value)>) -> Result<Self> {
+        let map = Self::default();
+        for (key, value) in entries {
+            map.set(token, &key, value)?;
+        }
+        Ok(map)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    pub fn get(&self, bracket: &Token, key: &Value) -> Result<Value> {
+        match self.entries.borrow().get(&Key::new(bracket, key)?) {
+            Some(value) => Ok(value.clone()),
+            None => Err(Error::Runtime(
+                bracket.clone(),
+                format!("Undefined key '{}'.", key),
+            )),
+        }
+    }
+
+    pub fn set(&self, bracket: &Token, key: &Value, value: Value) -> Result<()> {
+        let key = Key::new(bracket, key)?;
+        self.entries.borrow_mut().insert(key, value);
+        Ok(())
+    }
+
+    pub fn has(&self, token: &Token, key: &Value) -> Result<bool> {
+        Ok(self.entries.borrow().contains_key(&Key::new(token, key)?))
+    }
+
+    // Remove an entry, keeping the order of the others.
+    pub fn remove(&self, token: &Token, key: &Value) -> Result<Option<Value>> {
+        let key = Key::new(token, key)?;
+        Ok(self.entries.borrow_mut().shift_remove(&key))
+    }
+
+    pub fn keys(&self) -> Vec<Value> {
+        self.entries.borrow().keys().map(Key::to_value).collect()
+    }
+
+    pub fn values(&self) -> Vec<Value> {
+        self.entries.borrow().values().cloned().collect()
+    }
+}
+
+impl fmt::Display for LoxMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        value::display_nested(self, f, "{...}", |f| {
+            let entries: Vec<String> = self
+                .entries
+                .borrow()
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, value))
+                .collect();
+            write!(f, "{{{}}}", entries.join(", "))
+        })
+    }
+}
+
+// Maps are only equal to themselves
+impl cmp::PartialEq for LoxMap {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for LoxMap {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+impl Trace for LoxMap {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(entries) = self.entries.try_borrow() {
+            entries.values().for_each(|v| tracer.mark_value(v));
+        }
+    }
+
+    fn clear(&self) {
+        let entries = self
+            .entries
+            .try_borrow_mut()
+            .map(|mut e| mem::take(&mut *e));
+        drop(entries);
+    }
+
+    fn size(&self) -> usize {
+        let capacity = self.entries.try_borrow().map_or(0, |e| e.capacity());
+        mem::size_of::<Self>() + capacity * mem::size_of::<(Key, Value)>()
+    }
+}
+
+// Register the primitive functions working on maps.
+pub fn define_natives(interpreter: &mut Interpreter) -> Result<()> {
+    interpreter.define_native("keys", 1, keys)?;
+    interpreter.define_native("values", 1, values)?;
+    interpreter.define_native("has", 2, has)?;
+    interpreter.define_native("remove", 2, remove)?;
+    Ok(())
+}
+
+// keys(map) returns a list of the keys in insertion order.
+fn keys(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let map = as_map(interpreter, &arguments[0])?;
+    Ok(Value::from(map.keys()))
+}
+
+// values(map) returns a list of the values in insertion order.
+fn values(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let map = as_map(interpreter, &arguments[0])?;
+    Ok(Value::from(map.values()))
+}
+
+// has(map, key) tells whether the map contains the key.
+fn has(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let map = as_map(interpreter, &arguments[0])?;
+    let token = interpreter.call_site();
+    Ok(Value::Boolean(map.has(&token, &arguments[1])?))
+}
+
+// remove(map, key) removes the entry and returns its value, or nil if there was none.
+fn remove(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let map = as_map(interpreter, &arguments[0])?;
+    let token = interpreter.call_site();
+    Ok(map.remove(&token, &arguments[1])?.unwrap_or(Value::Nil))
+}
+
+fn as_map(interpreter: &Interpreter, value: &Value) -> Result<Rc<LoxMap>> {
+    match value {
+        Value::Map(map) => Ok(Rc::clone(map)),
+        _ => Err(interpreter.error("Argument must be a map.")),
+    }
+}
@@ -0,0 +1,70 @@
+use crate::environment::Environment;
+use crate::gc::Trace;
+use crate::gc::Tracer;
+use crate::result::Error;
+use crate::result::Result;
+use crate::token::Token;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+
+// Namespace holding the top-level bindings of an imported file
+#[derive(Debug)]
+pub struct LoxModule {
+    name: String,
+    environment: Rc<RefCell<Environment>>,
+}
+
+impl LoxModule {
+    pub fn new(name: &str, environment: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            name: name.to_string(),
+            environment,
+        }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value> {
+        match self.environment.borrow().get_local(&name.lexeme) {
+            Some(value) => Ok(value),
+            None => Err(Error::Runtime(
+                name.clone(),
+                format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LoxModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
+
+// Modules are only equal to themselves
+impl cmp::PartialEq for LoxModule {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for LoxModule {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+impl Trace for LoxModule {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.environment);
+    }
+
+    fn clear(&self) {}
+
+    fn size(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+}
@@ -1,31 +1,47 @@
 use crate::expr::Expr;
+use crate::literal::Literal;
 use crate::result::Error;
 use crate::result::Result;
 use crate::stmt::Stmt;
 use crate::token::Token;
 use crate::token_type::TokenType;
+use crate::token_type::KEYWORDS;
 use crate::value::Value;
+use std::mem;
+use std::path::Path;
 use tracing::debug;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<Error>,
+    // Number of loops around the current statement, inside the current function
+    loop_depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            loop_depth: 0,
+        }
     }
 
     pub fn run(&mut self) -> Result<Vec<Stmt>> {
         let mut statements = Vec::new();
 
         while !self.is_at_end() {
-            let s = self.declaration()?;
-            debug!("{s}");
-            statements.push(s);
+            if let Some(s) = self.declaration() {
+                debug!("{s}");
+                statements.push(s);
+            }
         }
 
+        if !self.errors.is_empty() {
+            return Err(Error::Multiple(self.errors.drain(..).collect()));
+        }
         Ok(statements)
     }
 
@@ -34,27 +50,62 @@ impl Parser {
         self.assignment()
     }
 
-    // declaration -> fun_decl | var_decl | statement ;
-    fn declaration(&mut self) -> Result<Stmt> {
-        let result = if self.is_match(&[TokenType::Fun]) {
+    // declaration -> class_decl | fun_decl | import_decl | var_decl | statement ;
+    // On a syntax error, record it and skip to the next statement.
+    fn declaration(&mut self) -> Option<Stmt> {
+        let result = if self.is_match(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.check(TokenType::Fun) && self.check_next(TokenType::Identifier) {
+            self.advance();
             self.function("function")
+        } else if self.is_match(&[TokenType::Import]) {
+            self.import_declaration()
         } else if self.is_match(&[TokenType::Var]) {
             self.var_declaration()
         } else {
             self.statement()
         };
         match result {
-            Ok(stmt) => Ok(stmt),
+            Ok(stmt) => Some(stmt),
             Err(error) => {
-                // TODO: Return null???
-                self.synchronize()?;
-                Err(error)
+                self.errors.push(error);
+                self.synchronize();
+                None
             }
         }
     }
 
-    // statement -> expr_stmt | for_stmt | if_stmt | print_stmt | return_stmt | while_stmt | block ;
+    // class_decl -> "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+    fn class_declaration(&mut self) -> Result<Stmt> {
+        let name = self
+            .consume(TokenType::Identifier, "Expect class name.")?
+            .clone();
+
+        let superclass = if self.is_match(&[TokenType::Less]) {
+            let superclass_name = self
+                .consume(TokenType::Identifier, "Expect superclass name.")?
+                .clone();
+            Some(Expr::Variable(superclass_name, None))
+        } else {
+            None
+        };
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+        Ok(Stmt::Class(name, superclass, methods))
+    }
+
+    // statement -> expr_stmt | break_stmt | continue_stmt | for_stmt | if_stmt
+    //            | print_stmt | return_stmt | throw_stmt | try_stmt | while_stmt | block ;
     fn statement(&mut self) -> Result<Stmt> {
+        if self.is_match(&[TokenType::Break, TokenType::Continue]) {
+            return self.jump_statement();
+        }
         if self.is_match(&[TokenType::For]) {
             return self.for_statement();
         }
@@ -67,6 +118,12 @@ impl Parser {
         if self.is_match(&[TokenType::Return]) {
             return self.return_statement();
         }
+        if self.is_match(&[TokenType::Throw]) {
+            return self.throw_statement();
+        }
+        if self.is_match(&[TokenType::Try]) {
+            return self.try_statement();
+        }
         if self.is_match(&[TokenType::While]) {
             return self.while_statement();
         }
@@ -100,18 +157,18 @@ impl Parser {
         }
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
-        let mut body = self.statement()?;
+        let mut body = self.loop_body()?;
 
-        // Desugar increment
-        if let Some(i) = increment {
-            body = Stmt::Block(Vec::from([body, Stmt::Expression(i)]));
-        }
-
-        // Desugar condition
+        // Desugar condition and increment.
+        // The increment stays apart from the body so that `continue` still runs it.
         if condition.is_none() {
             condition = Some(Box::new(Expr::Literal(Value::Boolean(true))));
         }
-        body = Stmt::While(condition.expect("Failed to get value"), Box::new(body));
+        body = Stmt::While(
+            condition.expect("Failed to get value"),
+            Box::new(body),
+            increment,
+        );
 
         // Desugar initializer
         if let Some(i) = initializer {
@@ -139,9 +196,10 @@ impl Parser {
 
     // print_stmt -> "print" expression ";" ;
     fn print_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
-        Ok(Stmt::Print(value))
+        Ok(Stmt::Print(keyword, value))
     }
 
     // return_stmt -> "return" expression? ";" ;
@@ -157,6 +215,79 @@ impl Parser {
         Ok(Stmt::Return(keyword, value))
     }
 
+    // throw_stmt -> "throw" expression ";" ;
+    fn throw_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw(keyword, value))
+    }
+
+    // try_stmt -> "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+    fn try_statement(&mut self) -> Result<Stmt> {
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.")?;
+        let body = self.block()?;
+
+        let mut handler = None;
+        if self.is_match(&[TokenType::Catch]) {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self
+                .consume(TokenType::Identifier, "Expect exception variable name.")?
+                .clone();
+            self.consume(
+                TokenType::RightParen,
+                "Expect ')' after exception variable.",
+            )?;
+            self.consume(TokenType::LeftBrace, "Expect '{' after catch clause.")?;
+            handler = Some((name, Box::new(self.block()?)));
+        }
+
+        let mut finally = None;
+        if self.is_match(&[TokenType::Finally]) {
+            self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'.")?;
+            finally = Some(Box::new(self.block()?));
+        }
+
+        if handler.is_none() && finally.is_none() {
+            return Err(Error::Parse(
+                self.peek().clone(),
+                "Expect 'catch' or 'finally' after try block.".to_string(),
+            ));
+        }
+        Ok(Stmt::Try(Box::new(body), handler, finally))
+    }
+
+    // import_decl -> "import" ( IDENTIFIER "from" )? STRING ";" ;
+    // Without a name, the module is bound to the file name without its extension.
+    fn import_declaration(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
+        let name = if self.is_match(&[TokenType::Identifier]) {
+            let name = self.previous().clone();
+            // `from` is only a keyword here, so it stays usable as a variable name.
+            if !(self.check(TokenType::Identifier) && self.peek().lexeme == "from") {
+                return Err(Error::Parse(
+                    self.peek().clone(),
+                    "Expect 'from' after module name.".to_string(),
+                ));
+            }
+            self.advance();
+            Some(name)
+        } else {
+            None
+        };
+
+        let path = self
+            .consume(TokenType::String, "Expect module path.")?
+            .clone();
+        let name = match name {
+            Some(name) => name,
+            None => module_name(&path)?,
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after module path.")?;
+        Ok(Stmt::Import(keyword, name, path))
+    }
+
     // var_decl -> "var" IDENTIFIER ( "=" expression )? ";" ;
     fn var_declaration(&mut self) -> Result<Stmt> {
         let name = self
@@ -181,9 +312,36 @@ impl Parser {
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
-        let body = self.statement()?;
+        let body = self.loop_body()?;
+
+        Ok(Stmt::While(condition, Box::new(body), None))
+    }
 
-        Ok(Stmt::While(condition, Box::new(body)))
+    fn loop_body(&mut self) -> Result<Stmt> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    // break_stmt -> "break" ";" ;
+    // continue_stmt -> "continue" ";" ;
+    fn jump_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().clone();
+        if self.loop_depth == 0 {
+            return Err(Error::Parse(
+                keyword.clone(),
+                format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            ));
+        }
+        self.consume(
+            TokenType::Semicolon,
+            &format!("Expect ';' after '{}'.", keyword.lexeme),
+        )?;
+        match keyword.token_type {
+            TokenType::Break => Ok(Stmt::Break(keyword)),
+            _ => Ok(Stmt::Continue(keyword)),
+        }
     }
 
     // expr_stmt -> expression ";" ;
@@ -203,6 +361,21 @@ impl Parser {
             TokenType::LeftParen,
             &format!("Expect '(' after {} name.", kind),
         )?;
+        let (parameters, body) = self.function_body(kind)?;
+        Ok(Stmt::Function(name, parameters, Box::new(body)))
+    }
+
+    // lambda -> "fun" "(" parameters? ")" block ;
+    fn lambda(&mut self) -> Result<Expr> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+        let (parameters, body) = self.function_body("function")?;
+        Ok(Expr::Function(keyword, parameters, Box::new(body)))
+    }
+
+    // Parameters and body of a function, after its opening parenthesis.
+    // parameters -> IDENTIFIER ( "," IDENTIFIER )* ;
+    fn function_body(&mut self, kind: &str) -> Result<(Vec<Token>, Stmt)> {
         let mut parameters = Vec::new();
         if !self.check(TokenType::RightParen) {
             loop {
@@ -229,8 +402,11 @@ impl Parser {
             TokenType::LeftBrace,
             &format!("Expect '{{' before {} body.", kind),
         )?;
-        let body = self.block()?;
-        Ok(Stmt::Function(name, parameters, Box::new(body)))
+        // Loops around the declaration can't be left from inside the body.
+        let enclosing_loops = mem::replace(&mut self.loop_depth, 0);
+        let body = self.block();
+        self.loop_depth = enclosing_loops;
+        Ok((parameters, body?))
     }
 
     // block -> "{" declaration* "}" ;
@@ -238,14 +414,17 @@ impl Parser {
         let mut statements = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
         }
 
         self.consume(TokenType::RightBrace, "Expect '}}' after block.")?;
         Ok(Stmt::Block(statements))
     }
 
-    // assignment -> IDENTIFIER "=" assignment | logic_or ;
+    // assignment -> ( call "." )? IDENTIFIER "=" assignment
+    //             | call "[" expression "]" "=" assignment | logic_or ;
     fn assignment(&mut self) -> Result<Box<Expr>> {
         let expr = self.or()?;
 
@@ -254,11 +433,15 @@ impl Parser {
             let value = self.assignment()?;
 
             match *expr {
-                Expr::Variable(name) => return Ok(Box::new(Expr::Assign(name, value))),
+                Expr::Variable(name, _) => return Ok(Box::new(Expr::Assign(name, value, None))),
+                Expr::Get(object, name) => return Ok(Box::new(Expr::Set(object, name, value))),
+                Expr::Index(object, bracket, index) => {
+                    return Ok(Box::new(Expr::SetIndex(object, bracket, index, value)))
+                }
                 _ => {
                     return Err(Error::Parse(
                         equals,
-                        "Invalid assignment target".to_string(),
+                        "Invalid assignment target.".to_string(),
                     ))
                 }
             }
@@ -383,7 +566,7 @@ impl Parser {
         Ok(Expr::Call(Box::new(callee), paren.clone(), arguments))
     }
 
-    // call -> primary ( "(" arguments? ")" )* ;
+    // call -> primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )* ;
     // arguments -> expression ( "," expression )* ;
     fn call(&mut self) -> Result<Box<Expr>> {
         let mut expr = self.primary()?;
@@ -391,6 +574,16 @@ impl Parser {
         loop {
             if self.is_match(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.is_match(&[TokenType::Dot]) {
+                let name = self
+                    .consume(TokenType::Identifier, "Expect property name after '.'.")?
+                    .clone();
+                expr = Expr::Get(Box::new(expr), name);
+            } else if self.is_match(&[TokenType::LeftBracket]) {
+                let bracket = self.previous().clone();
+                let index = self.expression()?;
+                self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index(Box::new(expr), bracket, index);
             } else {
                 break;
             }
@@ -399,12 +592,20 @@ impl Parser {
         Ok(Box::new(expr))
     }
 
-    // primary -> NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" | IDENTIFIER ;
+    // primary -> NUMBER | STRING | "true" | "false" | "nil" | "this"
+    //          | "(" expression ")" | IDENTIFIER | "super" "." IDENTIFIER
+    //          | lambda | "[" ( expression ( "," expression )* )? "]"
+    //          | "{" ( entry ( "," entry )* )? "}" | interpolation ;
+    // entry -> expression ":" expression ;
     fn primary(&mut self) -> Result<Expr> {
         if self.is_match(&[TokenType::Number, TokenType::String, TokenType::Nil]) {
             return Ok(Expr::Literal(self.previous().literal.clone().into()));
         }
 
+        if self.is_match(&[TokenType::Interpolation]) {
+            return self.interpolation();
+        }
+
         if self.is_match(&[TokenType::False]) {
             return Ok(Expr::Literal(Value::Boolean(false)));
         }
@@ -412,8 +613,27 @@ impl Parser {
             return Ok(Expr::Literal(Value::Boolean(true)));
         }
 
+        if self.is_match(&[TokenType::Super]) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self
+                .consume(TokenType::Identifier, "Expect superclass method name.")?
+                .clone();
+            return Ok(Expr::Super(keyword, method, None));
+        }
+
+        // A named function is a declaration, not an expression.
+        if self.check(TokenType::Fun) && !self.check_next(TokenType::Identifier) {
+            self.advance();
+            return self.lambda();
+        }
+
+        if self.is_match(&[TokenType::This]) {
+            return Ok(Expr::This(self.previous().clone(), None));
+        }
+
         if self.is_match(&[TokenType::Identifier]) {
-            return Ok(Expr::Variable(self.previous().clone()));
+            return Ok(Expr::Variable(self.previous().clone(), None));
         }
 
         if self.is_match(&[TokenType::LeftParen]) {
@@ -422,13 +642,66 @@ impl Parser {
             return Ok(Expr::Grouping(expr));
         }
 
+        if self.is_match(&[TokenType::LeftBracket]) {
+            let bracket = self.previous().clone();
+            let mut elements = Vec::new();
+            if !self.check(TokenType::RightBracket) {
+                loop {
+                    elements.push(*self.expression()?);
+                    if !self.is_match(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            return Ok(Expr::List(bracket, elements));
+        }
+
+        if self.is_match(&[TokenType::LeftBrace]) {
+            let brace = self.previous().clone();
+            let mut entries = Vec::new();
+            if !self.check(TokenType::RightBrace) {
+                loop {
+                    let key = *self.expression()?;
+                    self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                    entries.push((key, *self.expression()?));
+                    if !self.is_match(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+            return Ok(Expr::Map(brace, entries));
+        }
+
         Err(Error::Parse(
             self.peek().clone(),
             "Expect expression.".to_string(),
         ))
     }
 
-    fn synchronize(&mut self) -> Result<()> {
+    // interpolation -> INTERPOLATION expression ( INTERPOLATION expression )*
+    //                  INTERPOLATION_END ;
+    fn interpolation(&mut self) -> Result<Expr> {
+        let start = self.previous().clone();
+        let mut parts = vec![Expr::Literal(start.literal.clone().into())];
+        loop {
+            parts.push(*self.expression()?);
+            if self.is_match(&[TokenType::Interpolation]) {
+                parts.push(Expr::Literal(self.previous().literal.clone().into()));
+                continue;
+            }
+            let end = self.consume(
+                TokenType::InterpolationEnd,
+                "Expect '}' after interpolated expression.",
+            )?;
+            parts.push(Expr::Literal(end.literal.clone().into()));
+            return Ok(Expr::Interpolation(start, parts));
+        }
+    }
+
+    // Discard tokens until the beginning of the next statement.
+    fn synchronize(&mut self) {
         let t = [
             TokenType::Class,
             TokenType::Fun,
@@ -438,20 +711,23 @@ impl Parser {
             TokenType::While,
             TokenType::Print,
             TokenType::Return,
+            TokenType::Import,
+            TokenType::Throw,
+            TokenType::Try,
+            TokenType::Break,
+            TokenType::Continue,
         ];
 
         self.advance();
         while !self.is_at_end() {
             if self.previous().token_type == TokenType::Semicolon {
-                return Ok(());
+                return;
             }
             if t.contains(&self.peek().token_type) {
-                return Ok(());
+                return;
             }
             self.advance();
         }
-
-        Ok(())
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token> {
@@ -473,6 +749,13 @@ impl Parser {
         self.peek().token_type == token_type
     }
 
+    fn check_next(&self, token_type: TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.token_type == token_type,
+            None => false,
+        }
+    }
+
     // Consume the current token and return it.
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
@@ -496,3 +779,28 @@ impl Parser {
         self.peek().token_type == TokenType::Eof
     }
 }
+
+// Variable name for `import "path/to/name.lox";`
+fn module_name(path: &Token) -> Result<Token> {
+    let Literal::String(file) = &path.literal else {
+        unreachable!()
+    };
+    let stem = Path::new(file)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut chars = stem.chars();
+    let is_identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains_key(stem.as_str());
+    if !is_identifier {
+        return Err(Error::Parse(
+            path.clone(),
+            "Module file name is not an identifier. Use 'import name from'.".to_string(),
+        ));
+    }
+    Ok(Token::new(TokenType::Identifier, &stem, Literal::Nil, path.line).with_span(path.span))
+}
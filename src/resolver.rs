@@ -0,0 +1,337 @@
+use crate::expr::Expr;
+use crate::result::Error;
+use crate::result::Result;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// Static pass between the parser and the interpreter.
+// Records how many scopes away each local variable is declared.
+pub struct Resolver {
+    // Variable name -> whether its initializer has been resolved
+    scopes: Vec<HashMap<String, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+        }
+    }
+
+    pub fn run(&mut self, statements: &mut [Stmt]) -> Result<()> {
+        for statement in statements {
+            self.resolve_stmt(statement)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_stmt(&mut self, stmt: &mut Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Block(stmts) => {
+                self.begin_scope();
+                self.run(stmts)?;
+                self.end_scope();
+            }
+            Stmt::Break(_keyword) | Stmt::Continue(_keyword) => (),
+            Stmt::Class(name, superclass, methods) => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name)?;
+                self.define(name);
+
+                if let Some(s) = superclass {
+                    if let Expr::Variable(superclass_name, _) = s {
+                        if superclass_name.lexeme == name.lexeme {
+                            return Err(Error::Resolve(
+                                superclass_name.clone(),
+                                "A class can't inherit from itself.".to_string(),
+                            ));
+                        }
+                    }
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expr(s)?;
+
+                    // Methods of a subclass close over a scope that binds `super`.
+                    self.begin_scope();
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert("super".to_string(), true);
+                    }
+                }
+
+                // Methods close over a scope that binds `this`.
+                self.begin_scope();
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert("this".to_string(), true);
+                }
+                for method in methods {
+                    if let Stmt::Function(method_name, params, body) = method {
+                        let declaration = if method_name.lexeme == "init" {
+                            FunctionType::Initializer
+                        } else {
+                            FunctionType::Method
+                        };
+                        self.resolve_function(params, body, declaration)?;
+                    }
+                }
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+            Stmt::Expression(expr) => self.resolve_expr(expr)?,
+            Stmt::Function(name, params, body) => {
+                self.declare(name)?;
+                self.define(name);
+                self.resolve_function(params, body, FunctionType::Function)?;
+            }
+            Stmt::Import(_keyword, name, _path) => {
+                self.declare(name)?;
+                self.define(name);
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.resolve_expr(condition)?;
+                self.resolve_stmt(then_branch)?;
+                if let Some(b) = else_branch {
+                    self.resolve_stmt(b)?;
+                }
+            }
+            Stmt::Print(_keyword, expr) => self.resolve_expr(expr)?,
+            Stmt::Return(keyword, value) => {
+                if self.current_function == FunctionType::None {
+                    return Err(Error::Resolve(
+                        keyword.clone(),
+                        "Can't return from top-level code.".to_string(),
+                    ));
+                }
+                if let Some(v) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        return Err(Error::Resolve(
+                            keyword.clone(),
+                            "Can't return a value from an initializer.".to_string(),
+                        ));
+                    }
+                    self.resolve_expr(v)?;
+                }
+            }
+            Stmt::Throw(_keyword, value) => self.resolve_expr(value)?,
+            Stmt::Try(body, handler, finally) => {
+                self.resolve_stmt(body)?;
+                if let Some((name, handler)) = handler {
+                    // The caught value lives in a scope around the `catch` block.
+                    self.begin_scope();
+                    self.declare(name)?;
+                    self.define(name);
+                    self.resolve_stmt(handler)?;
+                    self.end_scope();
+                }
+                if let Some(f) = finally {
+                    self.resolve_stmt(f)?;
+                }
+            }
+            Stmt::Var(name, initializer) => {
+                self.declare(name)?;
+                if let Some(i) = initializer {
+                    self.resolve_expr(i)?;
+                }
+                self.define(name);
+            }
+            Stmt::While(condition, body, increment) => {
+                self.resolve_expr(condition)?;
+                self.resolve_stmt(body)?;
+                if let Some(i) = increment {
+                    self.resolve_expr(i)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_expr(&mut self, expr: &mut Expr) -> Result<()> {
+        match expr {
+            Expr::Assign(name, value, depth) => {
+                self.resolve_expr(value)?;
+                *depth = self.resolve_local(name);
+            }
+            Expr::Binary(left, _operator, right) => {
+                self.resolve_expr(left)?;
+                self.resolve_expr(right)?;
+            }
+            Expr::Call(callee, _paren, arguments) => {
+                self.resolve_expr(callee)?;
+                for argument in arguments {
+                    self.resolve_expr(argument)?;
+                }
+            }
+            Expr::Function(_keyword, params, body) => {
+                self.resolve_function(params, body, FunctionType::Function)?
+            }
+            Expr::Get(object, _name) => self.resolve_expr(object)?,
+            Expr::Grouping(expr) => self.resolve_expr(expr)?,
+            Expr::Index(object, _bracket, index) => {
+                self.resolve_expr(object)?;
+                self.resolve_expr(index)?;
+            }
+            Expr::Interpolation(_start, parts) => {
+                for part in parts {
+                    self.resolve_expr(part)?;
+                }
+            }
+            Expr::List(_bracket, elements) => {
+                for element in elements {
+                    self.resolve_expr(element)?;
+                }
+            }
+            Expr::Literal(_) => (),
+            Expr::Logical(left, _operator, right) => {
+                self.resolve_expr(left)?;
+                self.resolve_expr(right)?;
+            }
+            Expr::Map(_brace, entries) => {
+                for (key, value) in entries {
+                    self.resolve_expr(key)?;
+                    self.resolve_expr(value)?;
+                }
+            }
+            Expr::Set(object, _name, value) => {
+                self.resolve_expr(value)?;
+                self.resolve_expr(object)?;
+            }
+            Expr::SetIndex(object, _bracket, index, value) => {
+                self.resolve_expr(value)?;
+                self.resolve_expr(object)?;
+                self.resolve_expr(index)?;
+            }
+            Expr::Super(keyword, _method, depth) => {
+                match self.current_class {
+                    ClassType::None => {
+                        return Err(Error::Resolve(
+                            keyword.clone(),
+                            "Can't use 'super' outside of a class.".to_string(),
+                        ))
+                    }
+                    ClassType::Class => {
+                        return Err(Error::Resolve(
+                            keyword.clone(),
+                            "Can't use 'super' in a class with no superclass.".to_string(),
+                        ))
+                    }
+                    ClassType::Subclass => (),
+                }
+                *depth = self.resolve_local(keyword);
+            }
+            Expr::This(keyword, depth) => {
+                if self.current_class == ClassType::None {
+                    return Err(Error::Resolve(
+                        keyword.clone(),
+                        "Can't use 'this' outside of a class.".to_string(),
+                    ));
+                }
+                *depth = self.resolve_local(keyword);
+            }
+            Expr::Unary(_operator, right) => self.resolve_expr(right)?,
+            Expr::Variable(name, depth) => {
+                if let Some(false) = self.scopes.last().and_then(|s| s.get(&name.lexeme)) {
+                    return Err(Error::Resolve(
+                        name.clone(),
+                        "Can't read local variable in its own initializer.".to_string(),
+                    ));
+                }
+                *depth = self.resolve_local(name);
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_function(
+        &mut self,
+        params: &[Token],
+        body: &mut Stmt,
+        function_type: FunctionType,
+    ) -> Result<()> {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        // Parameters and body share one scope, like `LoxFunction::call`.
+        self.begin_scope();
+        for param in params {
+            self.declare(param)?;
+            self.define(param);
+        }
+        if let Stmt::Block(stmts) = body {
+            self.run(stmts)?;
+        }
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    // Add a variable to the innermost scope, marked as not ready yet.
+    fn declare(&mut self, name: &Token) -> Result<()> {
+        let Some(scope) = self.scopes.last_mut() else {
+            return Ok(());
+        };
+        if scope.contains_key(&name.lexeme) {
+            return Err(Error::Resolve(
+                name.clone(),
+                "Already a variable with this name in this scope.".to_string(),
+            ));
+        }
+        scope.insert(name.lexeme.clone(), false);
+        Ok(())
+    }
+
+    // Mark a variable as fully initialized and available for use.
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    // Number of scopes between the innermost one and the declaration.
+    // None means the variable is assumed to be global.
+    fn resolve_local(&self, name: &Token) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme))
+    }
+}
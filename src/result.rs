@@ -1,3 +1,5 @@
+use crate::token::source_id;
+use crate::token::Span;
 use crate::token::Token;
 use crate::token_type::TokenType;
 use crate::value::Value;
@@ -11,10 +13,13 @@ pub type Result<T> = result::Result<T, Error>;
 pub enum Error {
     Usage,
     IO(io::Error),
-    Lexical(usize, String), // Scanner
-    Parse(Token, String),   // Parser
-    Runtime(Token, String), // Interpreter
-    Return(Value),
+    Lexical(usize, Span, String), // Scanner
+    Parse(Token, String),         // Parser
+    Resolve(Token, String),       // Resolver
+    Runtime(Token, String),       // Interpreter
+    Throw(Token, Box<Value>),     // Uncaught `throw` statement
+    Multiple(Vec<Error>),         // Every static error found in a script
+    Bytecode(String),             // Invalid compiled file
 }
 
 impl From<io::Error> for Error {
@@ -23,13 +28,92 @@ impl From<io::Error> for Error {
     }
 }
 
+impl Error {
+    // Exit status following sysexits.h, as in the reference implementation.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage => 64,
+            Self::Lexical(..)
+            | Self::Parse(..)
+            | Self::Resolve(..)
+            | Self::Multiple(_)
+            | Self::Bytecode(_) => 65,
+            Self::Runtime(..) | Self::Throw(..) => 70,
+            Self::IO(_) => 74,
+        }
+    }
+}
+
+impl Error {
+    // The error followed by the source line it points at, with the offending
+    // lexeme underlined. `source` must be the code the error was found in.
+    pub fn render(&self, source: &str) -> String {
+        if let Self::Multiple(errors) = self {
+            let rendered: Vec<String> = errors.iter().map(|e| e.render(source)).collect();
+            return rendered.join("\n");
+        }
+        match self.snippet(source) {
+            Some(snippet) => format!("{self}\n{snippet}"),
+            None => self.to_string(),
+        }
+    }
+
+    // The quoted source line alone, if the error points into `source`.
+    pub fn snippet(&self, source: &str) -> Option<String> {
+        let span = match self {
+            Self::Lexical(_, span, _) => span,
+            Self::Parse(token, _)
+            | Self::Resolve(token, _)
+            | Self::Runtime(token, _)
+            | Self::Throw(token, _) => &token.span,
+            _ => return None,
+        };
+        // The error may come from other code, e.g. an imported module or a
+        // function defined on a previous line of the prompt. Tokens made up by
+        // the interpreter have no location at all.
+        if span.column == 0 || span.source != source_id(source) {
+            return None;
+        }
+        let head = source.get(..span.start)?;
+        let tail = source.get(span.start..)?;
+
+        let line_start = head.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = tail.find('\n').map_or(source.len(), |i| span.start + i);
+        let number = head.matches('\n').count() + 1;
+        let width = number.to_string().len();
+
+        // Keep tabs so that the underline lines up with the code above it.
+        let indent: String = head[line_start..]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let length = source
+            .get(span.start..span.end.min(line_end))?
+            .chars()
+            .count();
+        let underline = "^".repeat(length.max(1));
+
+        let text = source[line_start..line_end].trim_end_matches('\r');
+        Some(format!(
+            "{number:>width$} | {text}\n{:width$} | {indent}{underline}",
+            ""
+        ))
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Usage => write!(f, "Usage: rslox [script]"),
+            Self::Usage => write!(
+                f,
+                "Usage: rslox [--vm | --disassemble | --load] [script]\n       \
+                 rslox --compile script output"
+            ),
             Self::IO(error) => error.fmt(f),
-            Self::Lexical(line, message) => write!(f, "[line {}] Error: {}", line, message),
-            Self::Parse(token, message) => {
+            Self::Lexical(line, _span, message) => {
+                write!(f, "[line {}] Error: {}", line, message)
+            }
+            Self::Parse(token, message) | Self::Resolve(token, message) => {
                 let place = match token.token_type {
                     TokenType::Eof => " at end".to_string(),
                     _ => format!(" at '{}'", token.lexeme),
@@ -37,7 +121,12 @@ impl fmt::Display for Error {
                 write!(f, "[line {}] Error{}: {}", token.line, place, message)
             }
             Self::Runtime(token, message) => write!(f, "{}\n[line {}]", message, token.line),
-            Self::Return(_) => write!(f, "Unexpected return statement."),
+            Self::Throw(token, value) => write!(f, "{}\n[line {}]", value, token.line),
+            Self::Bytecode(message) => write!(f, "Error in compiled file: {}", message),
+            Self::Multiple(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
+            }
         }
     }
 }
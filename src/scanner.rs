@@ -1,6 +1,8 @@
 use crate::literal::Literal;
 use crate::result::Error;
 use crate::result::Result;
+use crate::token::source_id;
+use crate::token::Span;
 use crate::token::Token;
 use crate::token_type::TokenType;
 use crate::token_type::KEYWORDS;
@@ -10,40 +12,73 @@ use tracing::debug;
 pub struct Scanner {
     source: String,
     chars: Vec<char>,
+    // Byte offset of each character, followed by the length of the source
+    offsets: Vec<usize>,
+    // Identity of the source, stored in every span
+    source_id: u32,
     start: usize,
     current: usize,
     line: usize,
+    // Index of the first character of the current line
+    line_start: usize,
+    // Line and column of the first character of the current lexeme
+    start_line: usize,
+    column: usize,
+    // Number of unclosed `{` inside each unfinished `${` of an interpolated string
+    interpolations: Vec<usize>,
+    // Errors found inside a token that is still produced, such as a bad escape
+    errors: Vec<Error>,
 }
 
 impl Scanner {
     pub fn new(source: &str) -> Self {
+        let mut offsets: Vec<usize> = source.char_indices().map(|(i, _)| i).collect();
+        offsets.push(source.len());
         Scanner {
             source: source.to_string(),
             chars: source.chars().collect(),
+            offsets,
+            source_id: source_id(source),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            column: 1,
+            interpolations: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
-    pub fn run(&mut self) -> Result<Vec<Token>> {
+    // Scan the whole source, skipping over lexical errors so that all of them are
+    // reported and the tokens around them can still be parsed.
+    pub fn run(&mut self) -> (Vec<Token>, Vec<Error>) {
         let mut tokens = Vec::new();
+        let mut errors = Vec::new();
 
         while !self.is_at_end() {
             // At the beginning of the next lexeme.
             self.start = self.current;
+            self.start_line = self.line;
+            self.column = self.current - self.line_start + 1;
             match self.scan_token() {
                 Ok(Some(t)) => {
                     debug!("{t}");
                     tokens.push(t);
                 }
-                Err(e) => return Err(e),
+                Err(e) => errors.push(e),
                 _ => (),
             }
+            errors.append(&mut self.errors);
         }
-        tokens.push(Token::new(TokenType::Eof, "", Literal::Nil, self.line));
+        self.start = self.current;
+        self.start_line = self.line;
+        self.column = self.current - self.line_start + 1;
+        tokens.push(
+            Token::new(TokenType::Eof, "", Literal::Nil, self.start_line).with_span(self.span()),
+        );
 
-        Ok(tokens)
+        (tokens, errors)
     }
 
     fn scan_token(&mut self) -> Result<Option<Token>> {
@@ -52,8 +87,35 @@ impl Scanner {
         let token = match c {
             '(' => self.create_token(TokenType::LeftParen)?,
             ')' => self.create_token(TokenType::RightParen)?,
-            '{' => self.create_token(TokenType::LeftBrace)?,
-            '}' => self.create_token(TokenType::RightBrace)?,
+            '{' => {
+                if let Some(depth) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.create_token(TokenType::LeftBrace)?
+            }
+            '}' => match self.interpolations.last_mut() {
+                // The end of an interpolated expression, the string goes on.
+                Some(0) => {
+                    self.interpolations.pop();
+                    // Errors at the token point at the brace, not at the string after it.
+                    let brace = self.create_token(TokenType::RightBrace)?;
+                    let token = self.string_token(TokenType::InterpolationEnd)?;
+                    Token {
+                        lexeme: brace.lexeme,
+                        line: brace.line,
+                        span: brace.span,
+                        ..token
+                    }
+                }
+                Some(depth) => {
+                    *depth -= 1;
+                    self.create_token(TokenType::RightBrace)?
+                }
+                None => self.create_token(TokenType::RightBrace)?,
+            },
+            '[' => self.create_token(TokenType::LeftBracket)?,
+            ']' => self.create_token(TokenType::RightBracket)?,
+            ':' => self.create_token(TokenType::Colon)?,
             ',' => self.create_token(TokenType::Comma)?,
             '.' => self.create_token(TokenType::Dot)?,
             '-' => self.create_token(TokenType::Minus)?,
@@ -77,25 +139,26 @@ impl Scanner {
                 true => self.create_token(TokenType::GreaterEqual)?,
                 false => self.create_token(TokenType::Greater)?,
             },
-            '/' => match self.is_match('/') {
-                // A comment goes until the end of the line.
-                true => {
+            '/' => {
+                if self.is_match('/') {
+                    // A comment goes until the end of the line.
                     while self.peek()? != '\n' && !self.is_at_end() {
                         self.advance()?;
                     }
                     return Ok(None);
                 }
-                false => self.create_token(TokenType::Slash)?,
-            },
+                if self.is_match('*') {
+                    self.skip_block_comment()?;
+                    return Ok(None);
+                }
+                self.create_token(TokenType::Slash)?
+            }
             ' ' | '\r' | '\t' => return Ok(None), // Ignore whitespace.
             '\n' => {
-                self.line += 1;
+                self.new_line();
                 return Ok(None);
             }
-            '"' => match self.get_string()? {
-                Some(s) => self.create_token_with_literal(TokenType::String, Literal::String(s))?,
-                None => return Ok(None),
-            },
+            '"' => self.string_token(TokenType::String)?,
             _ => {
                 if is_digit(c) {
                     let n = self.get_number()?;
@@ -105,7 +168,8 @@ impl Scanner {
                     self.create_token(t)?
                 } else {
                     return Err(Error::Lexical(
-                        self.line,
+                        self.start_line,
+                        self.span(),
                         "Unexpected character.".to_string(),
                     ));
                 }
@@ -115,6 +179,29 @@ impl Scanner {
         Ok(Some(token))
     }
 
+    // Skip a block comment after its opening `/*`. Block comments nest, so that
+    // a region containing one can itself be commented out.
+    fn skip_block_comment(&mut self) -> Result<()> {
+        let opening = self.span();
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                return Err(Error::Lexical(
+                    self.start_line,
+                    opening,
+                    "Unterminated block comment.".to_string(),
+                ));
+            }
+            match self.advance()? {
+                '/' if self.is_match('*') => depth += 1,
+                '*' if self.is_match('/') => depth -= 1,
+                '\n' => self.new_line(),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
     fn get_identifier(&mut self) -> Result<TokenType> {
         while is_alpha_numeric(self.peek()?) {
             self.advance()?;
@@ -144,38 +231,107 @@ impl Scanner {
             }
         }
 
-        let value = self.source[self.start..self.current]
+        let value = self.source[self.offsets[self.start]..self.offsets[self.current]]
             .parse::<f64>()
             .unwrap();
         Ok(value)
     }
 
-    fn get_string(&mut self) -> Result<Option<String>> {
-        while self.peek()? != '"' && !self.is_at_end() {
-            if self.peek()? == '\n' {
-                self.line += 1;
+    // Scan the rest of a string, up to the closing `"` or to the next `${`.
+    // The part of an interpolated string that follows a `${` is scanned as an
+    // `Interpolation` token, and the part after the closing `}` gets `end_type`.
+    fn string_token(&mut self, end_type: TokenType) -> Result<Token> {
+        let (value, interpolated) = self.get_string()?;
+        let token_type = match interpolated {
+            true => TokenType::Interpolation,
+            false => end_type,
+        };
+        self.create_token_with_literal(token_type, Literal::String(value))
+    }
+
+    // Value of the string and whether it stopped at a `${`.
+    fn get_string(&mut self) -> Result<(String, bool)> {
+        let mut value = String::new();
+        loop {
+            if self.is_at_end() {
+                return Err(Error::Lexical(
+                    self.start_line,
+                    self.span(),
+                    "Unterminated string.".to_string(),
+                ));
+            }
+            match self.advance()? {
+                '"' => return Ok((value, false)),
+                '$' if self.is_match('{') => {
+                    self.interpolations.push(0);
+                    return Ok((value, true));
+                }
+                // At the end, the string is reported as unterminated instead.
+                '\\' if self.is_at_end() => (),
+                '\\' => match self.get_escape() {
+                    Ok(c) => value.push(c),
+                    // Keep scanning the string so that its end is not taken for a start.
+                    Err(e) => self.errors.push(e),
+                },
+                '\n' => {
+                    self.new_line();
+                    value.push('\n');
+                }
+                c => value.push(c),
             }
-            self.advance()?;
         }
+    }
 
-        if self.is_at_end() {
-            return Err(Error::Lexical(
-                self.line,
-                "Unterminated string.".to_string(),
-            ));
+    // Character of an escape sequence, just after its `\`.
+    fn get_escape(&mut self) -> Result<char> {
+        let start = self.current - 1;
+        let c = self.peek()?;
+        // A newline is left for the string to count.
+        if c != '\n' && !self.is_at_end() {
+            self.advance()?;
+        }
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            '$' => Ok('$'),
+            'u' => {
+                let code = match self.is_match('{') {
+                    true => self.get_hex_digits()?,
+                    false => None,
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| self.escape_error(start, "Invalid unicode escape sequence"))
+            }
+            _ => Err(self.escape_error(start, "Invalid escape sequence")),
         }
+    }
 
-        // The closing `"`.
-        self.advance()?;
+    // Code point of a `\u{...}` escape, made of one to six hex digits.
+    fn get_hex_digits(&mut self) -> Result<Option<u32>> {
+        let from = self.current;
+        while self.peek()?.is_ascii_hexdigit() {
+            self.advance()?;
+        }
+        let digits: String = self.chars[from..self.current].iter().collect();
+        if !self.is_match('}') || digits.is_empty() || digits.len() > 6 {
+            return Ok(None);
+        }
+        Ok(u32::from_str_radix(&digits, 16).ok())
+    }
 
-        // Trim the surrounding quotes.
-        let value = self
-            .chars
-            .get((self.start + 1)..(self.current - 1))
-            .expect("Failed to get a string from source.")
-            .iter()
-            .collect();
-        Ok(Some(value))
+    // Error located at the escape sequence starting at `start`.
+    fn escape_error(&self, start: usize, message: &str) -> Error {
+        let span = Span::new(
+            self.offsets[start],
+            self.offsets[self.current],
+            (start - self.line_start + 1) as u32,
+        )
+        .in_source(self.source_id);
+        let sequence = &self.source[span.start..span.end];
+        Error::Lexical(self.line, span, format!("{message} '{sequence}'."))
     }
 
     fn is_match(&mut self, expected: char) -> bool {
@@ -226,12 +382,29 @@ impl Scanner {
             .chars
             .get((self.start)..(self.current))
             .ok_or(Error::Lexical(
-                self.line,
+                self.start_line,
+                self.span(),
                 "Failed to get a lexeme.".to_string(),
             ))?
             .iter()
             .collect();
-        Ok(Token::new(token_type, &lexeme, literal, self.line))
+        Ok(Token::new(token_type, &lexeme, literal, self.start_line).with_span(self.span()))
+    }
+
+    // Location of the current lexeme
+    fn span(&self) -> Span {
+        Span::new(
+            self.offsets[self.start],
+            self.offsets[self.current],
+            self.column as u32,
+        )
+        .in_source(self.source_id)
+    }
+
+    // Called just after consuming a newline character.
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
     }
 }
 
@@ -251,38 +424,146 @@ fn is_alpha_numeric(c: char) -> bool {
 mod tests {
     use super::*;
 
+    // Scan `source`, checking that every span points into it. The source is
+    // then dropped from the spans so that they compare equal to `Span::new`.
+    fn scan(source: &str) -> (Vec<Token>, Vec<Error>) {
+        let (tokens, errors) = Scanner::new(source).run();
+        let tokens = tokens
+            .into_iter()
+            .map(|t| without_source(t, source))
+            .collect();
+        (tokens, errors)
+    }
+
+    fn scan_one(source: &str) -> Option<Token> {
+        let token = Scanner::new(source).scan_token().unwrap();
+        token.map(|t| without_source(t, source))
+    }
+
+    fn without_source(token: Token, source: &str) -> Token {
+        assert_eq!(token.span.source, source_id(source));
+        let span = token.span.in_source(0);
+        token.with_span(span)
+    }
+
     #[test]
     fn scan_tokens() {
         let src_addition = "1 + 2";
         let src_if_and_comment = "if (n1 + n2) <= 3 { // comment\n }";
 
+        let (tokens, errors) = scan(src_addition);
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(TokenType::Number, "1", Literal::Number(1f64), 1)
+                    .with_span(Span::new(0, 1, 1)),
+                Token::new(TokenType::Plus, "+", Literal::Nil, 1).with_span(Span::new(2, 3, 3)),
+                Token::new(TokenType::Number, "2", Literal::Number(2f64), 1)
+                    .with_span(Span::new(4, 5, 5)),
+                Token::new(TokenType::Eof, "", Literal::Nil, 1).with_span(Span::new(5, 5, 6))
+            ]
+        );
+        let (tokens, errors) = scan(src_if_and_comment);
+        assert!(errors.is_empty());
         assert_eq!(
-            Scanner::new(src_addition).run().unwrap(),
+            tokens,
             vec![
-                Token::new(TokenType::Number, "1", Literal::Number(1f64), 1),
-                Token::new(TokenType::Plus, "+", Literal::Nil, 1),
-                Token::new(TokenType::Number, "2", Literal::Number(2f64), 1),
-                Token::new(TokenType::Eof, "", Literal::Nil, 1)
+                Token::new(TokenType::If, "if", Literal::Nil, 1).with_span(Span::new(0, 2, 1)),
+                Token::new(TokenType::LeftParen, "(", Literal::Nil, 1)
+                    .with_span(Span::new(3, 4, 4)),
+                Token::new(TokenType::Identifier, "n1", Literal::Nil, 1)
+                    .with_span(Span::new(4, 6, 5)),
+                Token::new(TokenType::Plus, "+", Literal::Nil, 1).with_span(Span::new(7, 8, 8)),
+                Token::new(TokenType::Identifier, "n2", Literal::Nil, 1)
+                    .with_span(Span::new(9, 11, 10)),
+                Token::new(TokenType::RightParen, ")", Literal::Nil, 1)
+                    .with_span(Span::new(11, 12, 12)),
+                Token::new(TokenType::LessEqual, "<=", Literal::Nil, 1)
+                    .with_span(Span::new(13, 15, 14)),
+                Token::new(TokenType::Number, "3", Literal::Number(3f64), 1)
+                    .with_span(Span::new(16, 17, 17)),
+                Token::new(TokenType::LeftBrace, "{", Literal::Nil, 1)
+                    .with_span(Span::new(18, 19, 19)),
+                Token::new(TokenType::RightBrace, "}", Literal::Nil, 2)
+                    .with_span(Span::new(32, 33, 2)),
+                Token::new(TokenType::Eof, "", Literal::Nil, 2).with_span(Span::new(33, 33, 3))
             ]
         );
+    }
+
+    #[test]
+    fn scan_tokens_after_errors() {
+        let (tokens, errors) = scan("1 @ 2 #");
+        assert_eq!(errors.len(), 2);
         assert_eq!(
-            Scanner::new(src_if_and_comment).run().unwrap(),
+            tokens,
             vec![
-                Token::new(TokenType::If, "if", Literal::Nil, 1),
-                Token::new(TokenType::LeftParen, "(", Literal::Nil, 1),
-                Token::new(TokenType::Identifier, "n1", Literal::Nil, 1),
-                Token::new(TokenType::Plus, "+", Literal::Nil, 1),
-                Token::new(TokenType::Identifier, "n2", Literal::Nil, 1),
-                Token::new(TokenType::RightParen, ")", Literal::Nil, 1),
-                Token::new(TokenType::LessEqual, "<=", Literal::Nil, 1),
-                Token::new(TokenType::Number, "3", Literal::Number(3f64), 1),
-                Token::new(TokenType::LeftBrace, "{", Literal::Nil, 1),
-                Token::new(TokenType::RightBrace, "}", Literal::Nil, 2),
-                Token::new(TokenType::Eof, "", Literal::Nil, 2)
+                Token::new(TokenType::Number, "1", Literal::Number(1f64), 1)
+                    .with_span(Span::new(0, 1, 1)),
+                Token::new(TokenType::Number, "2", Literal::Number(2f64), 1)
+                    .with_span(Span::new(4, 5, 5)),
+                Token::new(TokenType::Eof, "", Literal::Nil, 1).with_span(Span::new(7, 7, 8))
             ]
         );
     }
 
+    #[test]
+    fn scan_spans_after_unicode() {
+        // Columns count characters, offsets count bytes.
+        let (tokens, errors) = scan("\"é\" + 12\n  x");
+        assert!(errors.is_empty());
+        let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 4, 1),
+                Span::new(5, 6, 5),
+                Span::new(7, 9, 7),
+                Span::new(12, 13, 3),
+                Span::new(13, 13, 4)
+            ]
+        );
+        assert_eq!(tokens[2].literal, Literal::Number(12f64));
+    }
+
+    #[test]
+    fn scan_string_escapes() {
+        let (tokens, errors) = scan(r#""a\tb\"\u{e9}" "\q""#);
+        assert_eq!(tokens[0].literal, Literal::String("a\tb\"é".to_string()));
+        assert_eq!(tokens[1].literal, Literal::String("".to_string()));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Invalid escape sequence '\\q'."
+        );
+    }
+
+    #[test]
+    fn scan_string_interpolation() {
+        let (tokens, errors) = scan(r#""a${ {} }b${c}d""#);
+        assert!(errors.is_empty());
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Interpolation,
+                TokenType::LeftBrace,
+                TokenType::RightBrace,
+                TokenType::Interpolation,
+                TokenType::Identifier,
+                TokenType::InterpolationEnd,
+                TokenType::Eof
+            ]
+        );
+        assert_eq!(tokens[0].literal, Literal::String("a".to_string()));
+        assert_eq!(tokens[3].literal, Literal::String("b".to_string()));
+        assert_eq!(tokens[5].literal, Literal::String("d".to_string()));
+        assert_eq!(tokens[3].lexeme, "}");
+        assert_eq!(tokens[5].lexeme, "}");
+        assert_eq!(tokens[5].span, Span::new(13, 14, 14));
+    }
+
     #[test]
     fn scan_token() {
         let src_plus = "+";
@@ -297,63 +578,74 @@ mod tests {
         let src_identifier = "tmp";
 
         assert_eq!(
-            Scanner::new(src_plus).scan_token().unwrap(),
-            Some(Token::new(TokenType::Plus, src_plus, Literal::Nil, 1))
+            scan_one(src_plus),
+            Some(
+                Token::new(TokenType::Plus, src_plus, Literal::Nil, 1)
+                    .with_span(Span::new(0, 1, 1))
+            )
         );
         assert_eq!(
-            Scanner::new(src_bang_equal).scan_token().unwrap(),
-            Some(Token::new(
-                TokenType::BangEqual,
-                src_bang_equal,
-                Literal::Nil,
-                1
-            ))
+            scan_one(src_bang_equal),
+            Some(
+                Token::new(TokenType::BangEqual, src_bang_equal, Literal::Nil, 1)
+                    .with_span(Span::new(0, 2, 1))
+            )
         );
-        assert_eq!(Scanner::new(src_whitespace).scan_token().unwrap(), None);
-        assert_eq!(Scanner::new(src_comment).scan_token().unwrap(), None);
+        assert_eq!(scan_one(src_whitespace), None);
+        assert_eq!(scan_one(src_comment), None);
         assert_eq!(
-            Scanner::new(src_slash).scan_token().unwrap(),
-            Some(Token::new(TokenType::Slash, src_slash, Literal::Nil, 1))
+            scan_one(src_slash),
+            Some(
+                Token::new(TokenType::Slash, src_slash, Literal::Nil, 1)
+                    .with_span(Span::new(0, 1, 1))
+            )
         );
         assert_eq!(
-            Scanner::new(src_string).scan_token().unwrap(),
-            Some(Token::new(
-                TokenType::String,
-                src_string,
-                Literal::String("string".to_string()),
-                1
-            ))
+            scan_one(src_string),
+            Some(
+                Token::new(
+                    TokenType::String,
+                    src_string,
+                    Literal::String("string".to_string()),
+                    1
+                )
+                .with_span(Span::new(0, 8, 1))
+            )
         );
         assert_eq!(
-            Scanner::new(src_string_with_newline).scan_token().unwrap(),
-            Some(Token::new(
-                TokenType::String,
-                src_string_with_newline,
-                Literal::String("string\nstring".to_string()),
-                2
-            ))
+            scan_one(src_string_with_newline),
+            Some(
+                Token::new(
+                    TokenType::String,
+                    src_string_with_newline,
+                    Literal::String("string\nstring".to_string()),
+                    1
+                )
+                .with_span(Span::new(0, 15, 1))
+            )
         );
         assert_eq!(
-            Scanner::new(src_decimal).scan_token().unwrap(),
-            Some(Token::new(
-                TokenType::Number,
-                src_decimal,
-                Literal::Number(src_decimal.parse::<f64>().unwrap()),
-                1
-            ))
+            scan_one(src_decimal),
+            Some(
+                Token::new(
+                    TokenType::Number,
+                    src_decimal,
+                    Literal::Number(src_decimal.parse::<f64>().unwrap()),
+                    1
+                )
+                .with_span(Span::new(0, 5, 1))
+            )
         );
         assert_eq!(
-            Scanner::new(src_or).scan_token().unwrap(),
-            Some(Token::new(TokenType::Or, src_or, Literal::Nil, 1))
+            scan_one(src_or),
+            Some(Token::new(TokenType::Or, src_or, Literal::Nil, 1).with_span(Span::new(0, 2, 1)))
         );
         assert_eq!(
-            Scanner::new(src_identifier).scan_token().unwrap(),
-            Some(Token::new(
-                TokenType::Identifier,
-                src_identifier,
-                Literal::Nil,
-                1
-            ))
+            scan_one(src_identifier),
+            Some(
+                Token::new(TokenType::Identifier, src_identifier, Literal::Nil, 1)
+                    .with_span(Span::new(0, 3, 1))
+            )
         );
     }
 }
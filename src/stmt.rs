@@ -2,16 +2,28 @@ use crate::expr::Expr;
 use crate::token::Token;
 use std::fmt;
 
+// Statements have no span of their own. Diagnostics point at one of their
+// tokens or expressions instead, e.g. the `print` keyword.
 #[derive(Clone, Debug)]
 pub enum Stmt {
     Block(Vec<Stmt>),
+    Break(Token),
+    Class(Token, Option<Expr>, Vec<Stmt>),
+    Continue(Token),
     Expression(Box<Expr>),
     Function(Token, Vec<Token>, Box<Stmt>),
     If(Box<Expr>, Box<Stmt>, Option<Box<Stmt>>),
-    Print(Box<Expr>),
+    // `import` keyword, name bound to the module and path string
+    Import(Token, Token, Token),
+    // `print` keyword and printed value
+    Print(Token, Box<Expr>),
     Return(Token, Option<Expr>),
+    Throw(Token, Box<Expr>),
+    // Body, `catch` variable and block, and `finally` block
+    Try(Box<Stmt>, Option<(Token, Box<Stmt>)>, Option<Box<Stmt>>),
     Var(Token, Option<Box<Expr>>),
-    While(Box<Expr>, Box<Stmt>),
+    // Condition, body and the increment clause of a `for` loop
+    While(Box<Expr>, Box<Stmt>, Option<Box<Expr>>),
 }
 
 impl fmt::Display for Stmt {
@@ -29,6 +41,18 @@ fn format_ast(stmt: &Stmt) -> String {
             }
             format!("(block {})", decls.join(" "))
         }
+        Stmt::Break(_keyword) => "(break)".to_string(),
+        Stmt::Class(name, superclass, methods) => {
+            let mut decls = vec![];
+            for method in methods {
+                decls.push(format_ast(method));
+            }
+            match superclass {
+                Some(s) => format!("(class {} < {} {})", name.lexeme, s, decls.join(" ")),
+                None => format!("(class {} {})", name.lexeme, decls.join(" ")),
+            }
+        }
+        Stmt::Continue(_keyword) => "(continue)".to_string(),
         Stmt::Expression(expr) => format!("(; {})", expr),
         Stmt::Function(name, params, body) => {
             let p = params
@@ -42,15 +66,30 @@ fn format_ast(stmt: &Stmt) -> String {
             Some(e) => format!("(if-else) {} {} {}", condition, then_branch, e),
             None => format!("(if {} {})", condition, then_branch),
         },
-        Stmt::Print(value) => format!("(print {})", value),
+        Stmt::Import(_keyword, name, path) => format!("(import {} {})", name.lexeme, path.lexeme),
+        Stmt::Print(_keyword, value) => format!("(print {})", value),
         Stmt::Return(_keyword, value) => match value {
             Some(v) => format!("(return {v})"),
             None => "(return)".to_string(),
         },
+        Stmt::Throw(_keyword, value) => format!("(throw {value})"),
+        Stmt::Try(body, handler, finally) => {
+            let mut s = format!("(try {}", format_ast(body));
+            if let Some((name, handler)) = handler {
+                s += &format!(" (catch {} {})", name.lexeme, format_ast(handler));
+            }
+            if let Some(f) = finally {
+                s += &format!(" (finally {})", format_ast(f));
+            }
+            s + ")"
+        }
         Stmt::Var(name, initializer) => match initializer {
             Some(i) => format!("(var {} = {})", name.lexeme, i),
             None => format!("(var {})", name.lexeme),
         },
-        Stmt::While(condition, body) => format!("(while {} {})", condition, format_ast(body)),
+        Stmt::While(condition, body, increment) => match increment {
+            Some(i) => format!("(while {} {} {})", condition, format_ast(body), i),
+            None => format!("(while {} {})", condition, format_ast(body)),
+        },
     }
 }
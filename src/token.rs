@@ -1,6 +1,54 @@
 use crate::literal::Literal;
 use crate::token_type::TokenType;
 use std::fmt;
+use std::hash::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+// Location of a piece of source code
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Span {
+    pub start: usize, // Byte offset of the first character
+    pub end: usize,   // Byte offset just past the last character
+    pub column: u32,  // Column of the first character, counted in characters from 1
+    pub source: u32,  // Source text the offsets point into, see `source_id`
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, column: u32) -> Self {
+        Self {
+            start,
+            end,
+            column,
+            source: 0,
+        }
+    }
+
+    pub fn in_source(self, source: u32) -> Self {
+        Self { source, ..self }
+    }
+
+    // Smallest span covering both spans.
+    pub fn merge(&self, other: &Span) -> Self {
+        if other.start < self.start {
+            return other.merge(self);
+        }
+        Self {
+            start: self.start,
+            end: self.end.max(other.end),
+            column: self.column,
+            source: self.source,
+        }
+    }
+}
+
+// Identity of a source text, kept in the spans of its tokens so that errors
+// are only quoted against the code they were found in.
+pub fn source_id(source: &str) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish() as u32
+}
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct Token {
@@ -8,6 +56,7 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Literal,
     pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
@@ -17,8 +66,13 @@ impl Token {
             lexeme: lexeme.to_string(),
             literal,
             line,
+            span: Span::default(),
         }
     }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Self { span, ..self }
+    }
 }
 
 impl fmt::Display for Token {
@@ -40,4 +94,12 @@ mod tests {
         );
         assert_eq!("String test test".to_string(), token.to_string());
     }
+
+    #[test]
+    fn merge_spans() {
+        let left = Span::new(4, 7, 5);
+        let right = Span::new(10, 12, 11);
+        assert_eq!(left.merge(&right), Span::new(4, 12, 5));
+        assert_eq!(right.merge(&left), Span::new(4, 12, 5));
+    }
 }
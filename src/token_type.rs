@@ -8,6 +8,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -30,22 +33,33 @@ pub enum TokenType {
     Identifier,
     String,
     Number,
+    // Part of a string before a `${`
+    Interpolation,
+    // Part of a string after the `}` closing an interpolated expression
+    InterpolationEnd,
 
     // Keyword
     And,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
     Eof,
@@ -54,19 +68,26 @@ pub enum TokenType {
 pub static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
     HashMap::from([
         ("and", TokenType::And),
+        ("break", TokenType::Break),
+        ("catch", TokenType::Catch),
         ("class", TokenType::Class),
+        ("continue", TokenType::Continue),
         ("else", TokenType::Else),
         ("false", TokenType::False),
+        ("finally", TokenType::Finally),
         ("fun", TokenType::Fun),
         ("for", TokenType::For),
         ("if", TokenType::If),
+        ("import", TokenType::Import),
         ("nil", TokenType::Nil),
         ("or", TokenType::Or),
         ("print", TokenType::Print),
         ("return", TokenType::Return),
         ("super", TokenType::Super),
         ("this", TokenType::This),
+        ("throw", TokenType::Throw),
         ("true", TokenType::True),
+        ("try", TokenType::Try),
         ("var", TokenType::Var),
         ("while", TokenType::While),
     ])
@@ -1,28 +1,85 @@
+use crate::callable::Callable;
+use crate::class::LoxClass;
+use crate::class::LoxInstance;
+use crate::exception::LoxException;
 use crate::function::LoxFunction;
 use crate::function::NativeFunction;
+use crate::gc;
+use crate::interner;
+use crate::list::LoxList;
 use crate::literal::Literal;
+use crate::map::LoxMap;
+use crate::module::LoxModule;
+use crate::vm::BoundMethod;
+use crate::vm::Closure;
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::fmt;
+use std::ptr;
+use std::rc::Rc;
 
+// Strings from the source are interned, so comparing them, e.g. a variable to a
+// string literal, usually stops at their addresses.
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub enum Value {
     Boolean(bool),
     Number(f64),
-    String(String),
+    String(Rc<str>),
     Nil,
     LoxFunction(LoxFunction),
     NativeFunction(NativeFunction),
+    LoxClass(Rc<LoxClass>),
+    LoxInstance(Rc<LoxInstance>),
+    List(Rc<LoxList>),
+    Map(Rc<LoxMap>),
+    Module(Rc<LoxModule>),
+    Exception(Rc<LoxException>),
+    Closure(Rc<Closure>),
+    BoundMethod(Rc<BoundMethod>),
 }
 
 impl From<Literal> for Value {
     fn from(value: Literal) -> Self {
         match value {
             Literal::Number(n) => Value::Number(n),
-            Literal::String(s) => Value::String(s),
+            Literal::String(s) => Value::String(interner::intern(&s)),
             Literal::Nil => Value::Nil,
         }
     }
 }
 
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+// Strings made at runtime are rarely compared to each other, so they are not
+// worth looking up.
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(Rc::from(value))
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(Rc::from(value))
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::List(gc::alloc(LoxList::new(value)))
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -32,11 +89,53 @@ impl fmt::Display for Value {
             Self::Nil => write!(f, "nil"),
             Self::LoxFunction(fun) => write!(f, "{fun}"),
             Self::NativeFunction(fun) => write!(f, "{fun}"),
+            Self::LoxClass(class) => write!(f, "{class}"),
+            Self::LoxInstance(instance) => write!(f, "{instance}"),
+            Self::List(list) => write!(f, "{list}"),
+            Self::Map(map) => write!(f, "{map}"),
+            Self::Module(module) => write!(f, "{module}"),
+            Self::Exception(exception) => write!(f, "{exception}"),
+            Self::Closure(closure) => write!(f, "{closure}"),
+            Self::BoundMethod(method) => write!(f, "{method}"),
         }
     }
 }
 
+thread_local! {
+    // Addresses of the lists and maps being displayed
+    static DISPLAYING: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+}
+
+// Display a list or a map with `write`, or with `placeholder` when it is
+// already being displayed further out, e.g. a list that contains itself.
+pub(crate) fn display_nested<T>(
+    object: &T,
+    f: &mut fmt::Formatter<'_>,
+    placeholder: &str,
+    write: impl FnOnce(&mut fmt::Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    let address = ptr::from_ref(object) as usize;
+    if !DISPLAYING.with_borrow_mut(|displaying| displaying.insert(address)) {
+        return f.write_str(placeholder);
+    }
+    let result = write(f);
+    DISPLAYING.with_borrow_mut(|displaying| displaying.remove(&address));
+    result
+}
+
 impl Value {
+    // Functions, natives and classes share one calling convention.
+    pub fn as_callable(&self) -> Option<&dyn Callable> {
+        match self {
+            Self::LoxFunction(fun) => Some(fun),
+            Self::NativeFunction(fun) => Some(fun),
+            Self::LoxClass(class) => Some(class),
+            Self::Closure(closure) => Some(closure),
+            Self::BoundMethod(method) => Some(method),
+            _ => None,
+        }
+    }
+
     // Only false and nil are falsey, everything else is truthy
     pub fn is_truthy(&self) -> bool {
         match self {
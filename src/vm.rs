@@ -0,0 +1,853 @@
+use crate::callable::Arity;
+use crate::callable::Callable;
+use crate::chunk::Constant;
+use crate::chunk::Function;
+use crate::chunk::OpCode;
+use crate::class::LoxClass;
+use crate::class::LoxInstance;
+use crate::disassembler;
+use crate::environment::Environment;
+use crate::gc;
+use crate::gc::Trace;
+use crate::gc::Tracer;
+use crate::interpreter;
+use crate::interpreter::Frame;
+use crate::interpreter::Interpreter;
+use crate::literal::Literal;
+use crate::map::LoxMap;
+use crate::result::Error;
+use crate::result::Result;
+use crate::token::Token;
+use crate::value::Value;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use tracing::debug;
+
+// Deepest nesting of calls before the script is stopped, counting the script.
+// The tree-walker stops at the same depth.
+pub(crate) const FRAMES_MAX: usize = 1024;
+
+// Function compiled to bytecode, with the variables it captured
+#[derive(Debug)]
+pub struct Closure {
+    function: Rc<Function>,
+    upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    // As in `LoxFunction`
+    globals: Rc<RefCell<Environment>>,
+}
+
+impl Closure {
+    pub fn name(&self) -> &str {
+        self.function.name()
+    }
+}
+
+impl Callable for Rc<Closure> {
+    fn arity(&self) -> Arity {
+        Arity::Fixed(self.function.arity)
+    }
+
+    // Calls from Rust run on a virtual machine of their own.
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+        Vm::new().call(interpreter, &Value::Closure(Rc::clone(self)), arguments)
+    }
+}
+
+impl fmt::Display for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+// Closures are only equal to themselves
+impl cmp::PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Closure {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, tracer: &mut Tracer) {
+        self.upvalues.iter().for_each(|u| tracer.mark(u));
+        tracer.mark(&self.globals);
+    }
+
+    fn clear(&self) {}
+
+    fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.upvalues.capacity() * mem::size_of::<Rc<()>>()
+    }
+}
+
+// Variable captured by a closure. It stays in its stack slot while the slot is
+// alive, and moves into the upvalue when its scope ends.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(upvalue) = self.try_borrow() {
+            if let Upvalue::Closed(value) = &*upvalue {
+                tracer.mark_value(value);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        let value = match self.try_borrow_mut() {
+            Ok(mut upvalue) => mem::replace(&mut *upvalue, Upvalue::Closed(Value::Nil)),
+            Err(_) => return,
+        };
+        drop(value);
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+}
+
+// Method of a class compiled to bytecode, bound to an instance
+#[derive(Debug)]
+pub struct BoundMethod {
+    receiver: Value,
+    method: Rc<Closure>,
+}
+
+impl BoundMethod {
+    pub fn new(receiver: Value, method: Rc<Closure>) -> Self {
+        Self { receiver, method }
+    }
+
+    pub fn name(&self) -> &str {
+        self.method.name()
+    }
+}
+
+impl Callable for Rc<BoundMethod> {
+    fn arity(&self) -> Arity {
+        self.method.arity()
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+        Vm::new().call(interpreter, &Value::BoundMethod(Rc::clone(self)), arguments)
+    }
+}
+
+impl fmt::Display for BoundMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.method)
+    }
+}
+
+// Bound methods are only equal to themselves
+impl cmp::PartialEq for BoundMethod {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for BoundMethod {
+    fn partial_cmp(&self, _: &Self) -> Option<cmp::Ordering> {
+        None
+    }
+}
+
+impl Trace for BoundMethod {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark_value(&self.receiver);
+        tracer.mark(&self.method);
+    }
+
+    fn clear(&self) {}
+
+    fn size(&self) -> usize {
+        mem::size_of::<Self>()
+    }
+}
+
+// Call of a closure in progress
+#[derive(Debug)]
+struct CallFrame {
+    closure: Rc<Closure>,
+    // Offset of the next instruction
+    ip: usize,
+    // Stack slot of the callee, followed by the arguments and the locals
+    base: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HandlerKind {
+    Catch,
+    Finally,
+}
+
+// `try` statement in progress, restoring the machine to the state it was in when
+// the statement started before jumping to its clause
+#[derive(Debug)]
+struct Handler {
+    kind: HandlerKind,
+    frames: usize,
+    stack: usize,
+    pending: usize,
+    // Offset of the clause in the chunk of the innermost frame
+    target: usize,
+}
+
+// Stack-based virtual machine running compiled functions.
+// The interpreter provides the globals, natives, output and imports.
+#[derive(Debug, Default)]
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    // Upvalues whose variable is still on the stack
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    handlers: Vec<Handler>,
+    // Errors waiting for a `finally` clause to end, with their traceback
+    pending: Vec<(Error, Vec<Frame>)>,
+    // Traceback of an error raised again after a `finally` clause
+    rethrown: Option<Vec<Frame>>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Run a compiled script and return the value it ends with.
+    pub fn run(&mut self, interpreter: &mut Interpreter, function: Rc<Function>) -> Result<Value> {
+        let closure = Closure {
+            function,
+            upvalues: Vec::new(),
+            globals: Rc::clone(&interpreter.globals),
+        };
+        self.call(interpreter, &Value::Closure(gc::alloc(closure)), &[])
+    }
+
+    // Call a value and run until the call returns.
+    pub fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        callee: &Value,
+        arguments: &[Value],
+    ) -> Result<Value> {
+        let depth = self.frames.len();
+        self.stack.push(callee.clone());
+        self.stack.extend_from_slice(arguments);
+        self.call_value(interpreter, arguments.len())?;
+        if self.frames.len() > depth {
+            self.execute(interpreter, depth)?;
+        }
+        Ok(self.pop())
+    }
+
+    // Run instructions until the frame above `depth` returns.
+    fn execute(&mut self, interpreter: &mut Interpreter, depth: usize) -> Result<()> {
+        loop {
+            match self.instruction(interpreter, depth) {
+                Ok(true) => return Ok(()),
+                Ok(false) => (),
+                Err(error) => self.unwind(interpreter, error, depth)?,
+            }
+        }
+    }
+
+    // Run one instruction, telling whether the frame above `depth` returned.
+    fn instruction(&mut self, interpreter: &mut Interpreter, depth: usize) -> Result<bool> {
+        debug!("{}", self.trace_stack());
+        debug!("{}", self.trace_instruction());
+        let byte = self.read_byte();
+        let Some(op) = OpCode::from_byte(byte) else {
+            return Err(self.error(interpreter, &format!("Unknown opcode {byte}.")));
+        };
+        match op {
+            OpCode::Constant => {
+                let value = match self.read_constant() {
+                    Constant::Number(n) => Value::Number(n),
+                    Constant::String(s) => Value::String(s),
+                    Constant::Function(_) => unreachable!(),
+                };
+                self.stack.push(value);
+            }
+            OpCode::Nil => self.stack.push(Value::Nil),
+            OpCode::True => self.stack.push(Value::Boolean(true)),
+            OpCode::False => self.stack.push(Value::Boolean(false)),
+            OpCode::Pop => {
+                self.pop();
+            }
+            OpCode::GetLocal => {
+                let slot = self.frame().base + self.read_u16() as usize;
+                self.stack.push(self.stack[slot].clone());
+            }
+            OpCode::SetLocal => {
+                let slot = self.frame().base + self.read_u16() as usize;
+                self.stack[slot] = self.peek(0).clone();
+            }
+            OpCode::GetGlobal => {
+                let name = self.read_string();
+                let value = self.frame().closure.globals.borrow().lookup(&name);
+                match value {
+                    Some(value) => self.stack.push(value),
+                    None => {
+                        let message = format!("Undefined variable '{}'.", name);
+                        return Err(self.error(interpreter, &message));
+                    }
+                }
+            }
+            OpCode::DefineGlobal => {
+                let name = self.read_string();
+                let value = self.pop();
+                self.frame().closure.globals.borrow().define(&name, value)?;
+            }
+            OpCode::SetGlobal => {
+                let name = self.read_string();
+                let value = self.peek(0).clone();
+                if !self.frame().closure.globals.borrow().update(&name, value) {
+                    let message = format!("Undefined variable '{}'.", name);
+                    return Err(self.error(interpreter, &message));
+                }
+            }
+            OpCode::GetUpvalue => {
+                let index = self.read_u16() as usize;
+                let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                let value = match &*upvalue.borrow() {
+                    Upvalue::Open(slot) => self.stack[*slot].clone(),
+                    Upvalue::Closed(value) => value.clone(),
+                };
+                self.stack.push(value);
+            }
+            OpCode::SetUpvalue => {
+                let index = self.read_u16() as usize;
+                let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                let value = self.peek(0).clone();
+                match &mut *upvalue.borrow_mut() {
+                    Upvalue::Open(slot) => self.stack[*slot] = value,
+                    Upvalue::Closed(closed) => *closed = value,
+                };
+            }
+            OpCode::GetProperty => {
+                let name = self.read_string();
+                let value = match self.pop() {
+                    Value::LoxInstance(instance) => LoxInstance::property(&instance, &name)?,
+                    Value::Exception(exception) => Some(exception.get(&self.token())?),
+                    Value::Module(module) => Some(module.get(&self.token())?),
+                    _ => return Err(self.error(interpreter, "Only instances have properties.")),
+                };
+                match value {
+                    Some(value) => self.stack.push(value),
+                    None => {
+                        let message = format!("Undefined property '{}'.", name);
+                        return Err(self.error(interpreter, &message));
+                    }
+                }
+            }
+            OpCode::SetProperty => {
+                let name = self.read_string();
+                let value = self.pop();
+                match self.pop() {
+                    Value::LoxInstance(instance) => instance.set(&name, value.clone()),
+                    _ => return Err(self.error(interpreter, "Only instances have fields.")),
+                }
+                self.stack.push(value);
+            }
+            OpCode::GetSuper => {
+                let name = self.read_string();
+                let superclass = self.pop();
+                let this = self.pop();
+                let method = match (superclass, this) {
+                    (Value::LoxClass(class), Value::LoxInstance(instance)) => {
+                        class.bind_method(&name, &instance)?
+                    }
+                    // Only a loaded file can get here, the compiler checks 'super'.
+                    _ => return Err(self.error(interpreter, "Invalid use of 'super'.")),
+                };
+                match method {
+                    Some(method) => self.stack.push(method),
+                    None => {
+                        let message = format!("Undefined property '{}'.", name);
+                        return Err(self.error(interpreter, &message));
+                    }
+                }
+            }
+            OpCode::GetIndex => {
+                let index = self.pop();
+                let value = match self.pop() {
+                    Value::List(list) => list.get(&self.token(), &index)?,
+                    Value::Map(map) => map.get(&self.token(), &index)?,
+                    _ => return Err(self.error(interpreter, "Only lists and maps can be indexed.")),
+                };
+                self.stack.push(value);
+            }
+            OpCode::SetIndex => {
+                let value = self.pop();
+                let index = self.pop();
+                match self.pop() {
+                    Value::List(list) => list.set(&self.token(), &index, value.clone())?,
+                    Value::Map(map) => map.set(&self.token(), &index, value.clone())?,
+                    _ => return Err(self.error(interpreter, "Only lists and maps can be indexed.")),
+                }
+                self.stack.push(value);
+            }
+            OpCode::Equal => {
+                let (a, b) = self.pop_pair();
+                self.stack.push(Value::Boolean(a == b));
+            }
+            OpCode::NotEqual => {
+                let (a, b) = self.pop_pair();
+                self.stack.push(Value::Boolean(a != b));
+            }
+            OpCode::Greater => self.comparison(interpreter, |a, b| a > b)?,
+            OpCode::GreaterEqual => self.comparison(interpreter, |a, b| a >= b)?,
+            OpCode::Less => self.comparison(interpreter, |a, b| a < b)?,
+            OpCode::LessEqual => self.comparison(interpreter, |a, b| a <= b)?,
+            OpCode::Add => match self.pop_pair() {
+                (Value::Number(a), Value::Number(b)) => self.stack.push(Value::Number(a + b)),
+                (Value::String(a), Value::String(b)) => {
+                    self.stack.push(Value::from([a, b].concat()))
+                }
+                _ => {
+                    return Err(
+                        self.error(interpreter, "Operands must be two numbers or two strings.")
+                    )
+                }
+            },
+            OpCode::Subtract => self.arithmetic(interpreter, |a, b| a - b)?,
+            OpCode::Multiply => self.arithmetic(interpreter, |a, b| a * b)?,
+            OpCode::Divide => self.arithmetic(interpreter, |a, b| a / b)?,
+            OpCode::Not => {
+                let value = self.pop();
+                self.stack.push(Value::Boolean(!value.is_truthy()));
+            }
+            OpCode::Negate => match self.pop() {
+                Value::Number(n) => self.stack.push(Value::Number(-n)),
+                _ => return Err(self.error(interpreter, "Operand must be a number.")),
+            },
+            OpCode::Print => {
+                let value = self.pop();
+                interpreter.print(&value)?;
+            }
+            OpCode::Jump => {
+                let offset = self.read_u16() as usize;
+                self.frame_mut().ip += offset;
+            }
+            OpCode::JumpIfFalse => {
+                let offset = self.read_u16() as usize;
+                if !self.peek(0).is_truthy() {
+                    self.frame_mut().ip += offset;
+                }
+            }
+            OpCode::Loop => {
+                let offset = self.read_u16() as usize;
+                self.frame_mut().ip -= offset;
+                gc::maybe_collect();
+            }
+            OpCode::Call => {
+                let count = self.read_byte() as usize;
+                self.call_value(interpreter, count)?;
+            }
+            OpCode::Closure => {
+                let Constant::Function(function) = self.read_constant() else {
+                    unreachable!()
+                };
+                let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                for _ in 0..function.upvalue_count {
+                    let is_local = self.read_byte() == 1;
+                    let index = self.read_u16() as usize;
+                    let upvalue = match is_local {
+                        true => self.capture_upvalue(self.frame().base + index),
+                        false => Rc::clone(&self.frame().closure.upvalues[index]),
+                    };
+                    upvalues.push(upvalue);
+                }
+                let closure = Closure {
+                    function,
+                    upvalues,
+                    globals: Rc::clone(&self.frame().closure.globals),
+                };
+                self.stack.push(Value::Closure(gc::alloc(closure)));
+            }
+            OpCode::CloseUpvalue => {
+                self.close_upvalues(self.stack.len() - 1);
+                self.pop();
+            }
+            OpCode::Return => {
+                let result = self.pop();
+                let frame = self.frames.pop().expect("Missing call frame.");
+                self.close_upvalues(frame.base);
+                self.stack.truncate(frame.base);
+                self.stack.push(result);
+                return Ok(self.frames.len() == depth);
+            }
+            OpCode::Class | OpCode::Subclass => {
+                let name = self.read_string();
+                let count = self.read_u16() as usize;
+                let mut methods = HashMap::new();
+                for method in self.stack.split_off(self.stack.len() - count) {
+                    if let Value::Closure(closure) = &method {
+                        methods.insert(closure.name().to_string(), method);
+                    }
+                }
+                // The superclass stays below, in the local that methods refer to.
+                let superclass = match (op, self.peek(0)) {
+                    (OpCode::Subclass, Value::LoxClass(class)) => Some(Rc::clone(class)),
+                    _ => None,
+                };
+                let class = LoxClass::new(&name, superclass, methods);
+                self.stack.push(Value::LoxClass(gc::alloc(class)));
+            }
+            OpCode::Superclass => {
+                if !matches!(self.peek(0), Value::LoxClass(_)) {
+                    return Err(self.error(interpreter, "Superclass must be a class."));
+                }
+            }
+            OpCode::List => {
+                let count = self.read_u16() as usize;
+                let elements = self.stack.split_off(self.stack.len() - count);
+                self.stack.push(Value::from(elements));
+            }
+            OpCode::Map => {
+                let count = self.read_u16() as usize;
+                let values = self.stack.split_off(self.stack.len() - 2 * count);
+                let mut entries = Vec::with_capacity(count);
+                let mut values = values.into_iter();
+                while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                    entries.push((key, value));
+                }
+                let map = LoxMap::new(&self.token(), entries)?;
+                self.stack.push(Value::Map(gc::alloc(map)));
+            }
+            OpCode::Interpolate => {
+                let count = self.read_u16() as usize;
+                let mut s = String::new();
+                for part in self.stack.split_off(self.stack.len() - count) {
+                    s.push_str(&part.to_string());
+                }
+                self.stack.push(Value::from(s));
+            }
+            OpCode::Import => {
+                let path = Literal::String(self.read_string().to_string());
+                let token = Token {
+                    literal: path,
+                    ..self.token()
+                };
+                let module = interpreter.import(&token)?;
+                self.stack.push(Value::Module(module));
+            }
+            OpCode::Throw => {
+                let value = self.pop();
+                return Err(Error::Throw(self.token(), Box::new(value)));
+            }
+            OpCode::PushCatch | OpCode::PushFinally => {
+                let offset = self.read_u16() as usize;
+                let kind = match op {
+                    OpCode::PushCatch => HandlerKind::Catch,
+                    _ => HandlerKind::Finally,
+                };
+                self.handlers.push(Handler {
+                    kind,
+                    frames: self.frames.len(),
+                    stack: self.stack.len(),
+                    pending: self.pending.len(),
+                    target: self.frame().ip + offset,
+                });
+            }
+            OpCode::PopHandler => {
+                self.handlers.pop();
+            }
+            OpCode::Rethrow => {
+                let (error, trace) = self.pending.pop().expect("Missing pending error.");
+                self.rethrown = Some(trace);
+                return Err(error);
+            }
+            OpCode::DropError => {
+                self.pending.pop();
+            }
+        }
+        Ok(false)
+    }
+
+    // Call the value below the arguments on top of the stack.
+    // A closure gets a new frame, anything else is called right away.
+    fn call_value(&mut self, interpreter: &mut Interpreter, count: usize) -> Result<()> {
+        let slot = self.stack.len() - count - 1;
+        match self.stack[slot].clone() {
+            Value::Closure(closure) => self.call_closure(interpreter, closure, count),
+            Value::BoundMethod(bound) => {
+                self.stack[slot] = bound.receiver.clone();
+                self.call_closure(interpreter, Rc::clone(&bound.method), count)
+            }
+            Value::LoxClass(class) => {
+                let initializer = match class.find_method("init") {
+                    Some(Value::Closure(initializer)) => Some(Rc::clone(initializer)),
+                    // An initializer declared while running on the tree-walker
+                    Some(_) => {
+                        return self.call_callable(interpreter, &Value::LoxClass(class), count)
+                    }
+                    None => None,
+                };
+                let instance = LoxInstance::new(Rc::clone(&class));
+                self.stack[slot] = Value::LoxInstance(gc::alloc(instance));
+                match initializer {
+                    Some(initializer) => self.call_closure(interpreter, initializer, count),
+                    None if count != 0 => Err(self.error(
+                        interpreter,
+                        &format!("Expected {} but got {}.", Arity::Fixed(0), count),
+                    )),
+                    None => Ok(()),
+                }
+            }
+            callee => self.call_callable(interpreter, &callee, count),
+        }
+    }
+
+    // Call a value that runs outside the VM, such as a native, with the
+    // arguments on top of the stack, and leave its result in their place.
+    fn call_callable(
+        &mut self,
+        interpreter: &mut Interpreter,
+        callee: &Value,
+        count: usize,
+    ) -> Result<()> {
+        let Some(function) = callee.as_callable() else {
+            return Err(self.error(interpreter, "Can only call functions and classes."));
+        };
+        if !function.arity().accepts(count) {
+            let message = format!("Expected {} but got {}.", function.arity(), count);
+            return Err(self.error(interpreter, &message));
+        }
+        let arguments = self.stack.split_off(self.stack.len() - count);
+        self.pop();
+        // Natives report their errors at the call.
+        let call_site = self.location(interpreter);
+        let enclosing_call = interpreter.call_site.replace(call_site);
+        let result = function.call(interpreter, &arguments);
+        interpreter.call_site = enclosing_call;
+        self.stack.push(result?);
+        Ok(())
+    }
+
+    fn call_closure(
+        &mut self,
+        interpreter: &Interpreter,
+        closure: Rc<Closure>,
+        count: usize,
+    ) -> Result<()> {
+        if count != closure.function.arity {
+            let message = format!(
+                "Expected {} but got {}.",
+                Arity::Fixed(closure.function.arity),
+                count
+            );
+            return Err(self.error(interpreter, &message));
+        }
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error(interpreter, "Stack overflow."));
+        }
+        gc::maybe_collect();
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: self.stack.len() - count - 1,
+        });
+        Ok(())
+    }
+
+    // Jump to the innermost handler that accepts the error, or give the error
+    // back once the machine is as it was before the call above `depth`.
+    fn unwind(&mut self, interpreter: &mut Interpreter, error: Error, depth: usize) -> Result<()> {
+        let trace = match self.rethrown.take() {
+            Some(trace) => trace,
+            None => self.traceback(interpreter),
+        };
+
+        while self.handlers.last().is_some_and(|h| h.frames > depth) {
+            let handler = self.handlers.pop().expect("Missing handler.");
+            let caught = match handler.kind {
+                HandlerKind::Catch => match interpreter::caught(&error) {
+                    Some(value) => Some(value),
+                    None => continue,
+                },
+                HandlerKind::Finally => None,
+            };
+
+            self.frames.truncate(handler.frames);
+            self.close_upvalues(handler.stack);
+            self.stack.truncate(handler.stack);
+            self.pending.truncate(handler.pending);
+            self.frame_mut().ip = handler.target;
+            match caught {
+                Some(value) => {
+                    // See `Interpreter::trace`
+                    interpreter.trace = None;
+                    self.stack.push(value);
+                }
+                None => self.pending.push((error, trace)),
+            }
+            return Ok(());
+        }
+
+        let base = self.frames[depth].base;
+        self.close_upvalues(base);
+        self.stack.truncate(base);
+        self.frames.truncate(depth);
+        if interpreter.trace.is_none() {
+            interpreter.trace = Some(trace);
+        }
+        Err(error)
+    }
+
+    // Calls in progress, as the interpreter records them for tracebacks.
+    // The first frame is the script, or a call from Rust the interpreter records.
+    fn traceback(&self, interpreter: &Interpreter) -> Vec<Frame> {
+        let mut trace = interpreter.frames.clone();
+        for (caller, frame) in self.frames.iter().zip(self.frames.iter().skip(1)) {
+            let chunk = &caller.closure.function.chunk;
+            trace.push(Frame {
+                function: frame.closure.name().to_string(),
+                call_site: chunk.token(caller.ip - 1).clone(),
+            });
+        }
+        trace
+    }
+
+    // Values on the stack, bottom first:
+    //   [ <fn fib> ][ 10 ]
+    fn trace_stack(&self) -> String {
+        let slots: String = self.stack.iter().map(|v| format!("[ {} ]", v)).collect();
+        format!("          {}", slots)
+    }
+
+    fn trace_instruction(&self) -> String {
+        let frame = self.frame();
+        disassembler::instruction(&frame.closure.function.chunk, frame.ip).0
+    }
+
+    // Reuse the upvalue of a slot if a closure already captured it.
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|u| matches!(*u.borrow(), Upvalue::Open(s) if s == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+        let upvalue = gc::alloc(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // Move the variables of the slots from `start` up into their upvalues.
+    fn close_upvalues(&mut self, start: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= start => {
+                    let value = stack.get(slot).cloned().unwrap_or(Value::Nil);
+                    *upvalue = Upvalue::Closed(value);
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn arithmetic(&mut self, interpreter: &Interpreter, f: fn(f64, f64) -> f64) -> Result<()> {
+        match self.pop_pair() {
+            (Value::Number(a), Value::Number(b)) => {
+                self.stack.push(Value::Number(f(a, b)));
+                Ok(())
+            }
+            _ => Err(self.error(interpreter, "Operands must be numbers.")),
+        }
+    }
+
+    fn comparison(&mut self, interpreter: &Interpreter, f: fn(f64, f64) -> bool) -> Result<()> {
+        match self.pop_pair() {
+            (Value::Number(a), Value::Number(b)) => {
+                self.stack.push(Value::Boolean(f(a, b)));
+                Ok(())
+            }
+            _ => Err(self.error(interpreter, "Operands must be numbers.")),
+        }
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("Missing call frame.")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("Missing call frame.")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let operand = frame.closure.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        operand
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+        self.frame().closure.function.chunk.constants[index].clone()
+    }
+
+    fn read_string(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::String(s) => s,
+            _ => unreachable!(),
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("Stack underflow.")
+    }
+
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let b = self.pop();
+        let a = self.pop();
+        (a, b)
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    // Token of the instruction being run.
+    fn token(&self) -> Token {
+        let frame = self.frame();
+        frame.closure.function.chunk.token(frame.ip - 1).clone()
+    }
+
+    // Token of the instruction being run, or of the call from Rust before the
+    // first instruction.
+    fn location(&self, interpreter: &Interpreter) -> Token {
+        match self.frames.last() {
+            Some(frame) => frame.closure.function.chunk.token(frame.ip - 1).clone(),
+            None => interpreter.call_site(),
+        }
+    }
+
+    fn error(&self, interpreter: &Interpreter, message: &str) -> Error {
+        Error::Runtime(self.location(interpreter), message.to_string())
+    }
+}
@@ -0,0 +1,231 @@
+use std::fs;
+use std::process::Command;
+
+// What a script is expected to do, read from its comments:
+//   // expect: <stdout line>
+//   // expect runtime error: <message>
+//   // Error ...              (compile error on this line)
+//   // [line N] Error ...     (compile error on line N)
+//   // [java line N] Error ...
+// `[c line N]` annotations belong to the bytecode implementation and are ignored.
+#[derive(Debug, Default)]
+struct Expectation {
+    output: Vec<String>,
+    errors: Vec<String>,
+    runtime_error: Option<(String, usize)>,
+}
+
+impl Expectation {
+    fn parse(source: &str) -> Self {
+        let mut expectation = Self::default();
+
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+
+            if let Some(output) = find_after(line, "// expect: ") {
+                expectation.output.push(output.to_string());
+            } else if let Some(message) = find_after(line, "// expect runtime error: ") {
+                expectation.runtime_error = Some((message.to_string(), line_number));
+            } else if let Some(error) = find_after(line, "// Error") {
+                expectation
+                    .errors
+                    .push(format!("[line {line_number}] Error{error}"));
+            } else if let Some(annotation) = find_after(line, "// [") {
+                if let Some(error) = parse_line_annotation(annotation) {
+                    expectation.errors.push(error);
+                }
+            }
+        }
+
+        expectation
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            65
+        } else if self.runtime_error.is_some() {
+            70
+        } else {
+            0
+        }
+    }
+
+    fn stderr(&self) -> Vec<String> {
+        match &self.runtime_error {
+            Some((message, line)) if self.errors.is_empty() => {
+                vec![message.clone(), format!("[line {line}]")]
+            }
+            _ => self.errors.clone(),
+        }
+    }
+}
+
+fn find_after<'a>(line: &'a str, pattern: &str) -> Option<&'a str> {
+    line.find(pattern).map(|i| &line[i + pattern.len()..])
+}
+
+// Parse the rest of `// [line N] Error ...` or `// [java line N] Error ...`.
+fn parse_line_annotation(annotation: &str) -> Option<String> {
+    let (place, error) = annotation.split_once("] ")?;
+    let line_number = match place.split_once(' ') {
+        Some(("line", n)) => n,
+        Some(("java", rest)) => rest.strip_prefix("line ")?,
+        _ => return None,
+    };
+    let line_number: usize = line_number.parse().ok()?;
+    if !error.starts_with("Error") {
+        return None;
+    }
+    Some(format!("[line {line_number}] {error}"))
+}
+
+// Flags selecting each backend of the interpreter binary.
+// The bytecode backend compiles the script to a file first, then loads it.
+const BACKENDS: [(&str, &[&str]); 3] = [
+    ("tree-walker", &[]),
+    ("vm", &["--vm"]),
+    ("bytecode", &["--load"]),
+];
+
+// Scripts that import their own file, which a compiled file is not
+const NOT_COMPILED: [&str; 1] = ["err_self_import.lox"];
+
+// Run the interpreter binary on a script, returning stdout, stderr and the exit code.
+fn run(path: &str, flags: &[&str]) -> (String, String, i32) {
+    let command = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rslox"))
+            .args(args)
+            .output()
+            .expect("Failed to run the interpreter.");
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+            output.status.code().unwrap_or(-1),
+        )
+    };
+    if flags != ["--load"] {
+        return command(&[flags, &[path]].concat());
+    }
+
+    // Next to the script, so that imports resolve the same way.
+    let compiled = format!("{path}c");
+    let result = match command(&["--compile", path, &compiled]) {
+        (stdout, stderr, 0) => {
+            let (more, errors, exit_code) = command(&["--load", &compiled]);
+            (stdout + &more, stderr + &errors, exit_code)
+        }
+        failed => failed,
+    };
+    let _ = fs::remove_file(&compiled);
+    result
+}
+
+// Run a script with the interpreter binary and describe every way it differs from
+// the expectations written in the script.
+fn mismatches(path: &str, backend: &str, flags: &[&str]) -> Vec<String> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return vec![format!("{path}: failed to read: {e}")],
+    };
+    let expectation = Expectation::parse(&source);
+
+    let (stdout, stderr, exit_code) = run(path, flags);
+
+    let path = format!("{path} ({backend})");
+    let path = path.as_str();
+    let mut mismatches = Vec::new();
+    compare(
+        path,
+        "stdout",
+        &expectation.output,
+        &stdout.lines().collect::<Vec<_>>(),
+        &mut mismatches,
+    );
+    compare(
+        path,
+        "stderr",
+        &expectation.stderr(),
+        &without_traceback(stderr.lines().filter(|l| !is_snippet(l))),
+        &mut mismatches,
+    );
+    if exit_code != expectation.exit_code() {
+        mismatches.push(format!(
+            "{path}: expected exit code {} but got {exit_code}",
+            expectation.exit_code()
+        ));
+    }
+
+    mismatches
+}
+
+// Diagnostics quote the offending source line below each error:
+//   3 | a + b = "value";
+//     |       ^
+// Annotations only describe the error itself, so these lines are skipped.
+fn is_snippet(line: &str) -> bool {
+    let gutter = line
+        .trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim_start();
+    gutter.starts_with("| ") && gutter != line
+}
+
+// Uncaught runtime errors print a traceback:
+//   [line 12] in fib()
+//   [line 30] in script
+// Annotations only give the line of the error, so the first frame is reduced to
+// `[line N]` and the outer ones are skipped, as are the counts of repeated frames.
+fn without_traceback<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut in_traceback = false;
+    let mut result = Vec::new();
+    for line in lines {
+        match line.split_once("] in ") {
+            Some((place, _)) if place.starts_with("[line ") => {
+                if !in_traceback {
+                    result.push(&line[..place.len() + 1]);
+                }
+                in_traceback = true;
+            }
+            _ if in_traceback && line.starts_with("... ") => (),
+            _ => result.push(line),
+        }
+    }
+    result
+}
+
+fn compare(
+    path: &str,
+    stream: &str,
+    expected: &[String],
+    actual: &[&str],
+    mismatches: &mut Vec<String>,
+) {
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => (),
+            (Some(e), Some(a)) => mismatches.push(format!(
+                "{path}: {stream} line {}: expected '{e}' but got '{a}'",
+                i + 1
+            )),
+            (Some(e), None) => mismatches.push(format!("{path}: {stream} missing expected '{e}'")),
+            (None, Some(a)) => mismatches.push(format!("{path}: {stream} unexpected '{a}'")),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+// Check every script and fail with a report of all mismatches.
+pub fn check(paths: &[&str]) {
+    let mismatches: Vec<String> = paths
+        .iter()
+        .flat_map(|path| {
+            BACKENDS
+                .iter()
+                .filter(|(_, flags)| {
+                    *flags != ["--load"] || !NOT_COMPILED.iter().any(|s| path.ends_with(s))
+                })
+                .flat_map(|(backend, flags)| mismatches(path, backend, flags))
+        })
+        .collect();
+    assert!(mismatches.is_empty(), "\n{}\n", mismatches.join("\n"));
+}
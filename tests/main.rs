@@ -1,228 +1,478 @@
-mod tests {
-    extern crate rslox;
+mod common;
 
-    use rslox::lox::Lox;
+mod tests {
+    use crate::common::check;
 
     #[test]
     fn check_assignment() {
-        assert!(Lox::run_file("examples/assignment/err_grouping.lox").is_err());
-        assert!(Lox::run_file("examples/assignment/err_infix_operator.lox").is_err());
-        assert!(Lox::run_file("examples/assignment/err_prefix_operator.lox").is_err());
-        assert!(Lox::run_file("examples/assignment/err_to_this.lox").is_err());
-        assert!(Lox::run_file("examples/assignment/err_undefined.lox").is_err());
-        assert!(Lox::run_file("examples/assignment/ok_associativity.lox").is_ok());
-        assert!(Lox::run_file("examples/assignment/ok_global.lox").is_ok());
-        assert!(Lox::run_file("examples/assignment/ok_local.lox").is_ok());
-        assert!(Lox::run_file("examples/assignment/ok_syntax.lox").is_ok());
+        check(&[
+            "examples/assignment/err_grouping.lox",
+            "examples/assignment/err_infix_operator.lox",
+            "examples/assignment/err_prefix_operator.lox",
+            "examples/assignment/err_to_this.lox",
+            "examples/assignment/err_undefined.lox",
+            "examples/assignment/ok_associativity.lox",
+            "examples/assignment/ok_global.lox",
+            "examples/assignment/ok_local.lox",
+            "examples/assignment/ok_syntax.lox",
+        ]);
     }
 
     #[test]
     fn check_block() {
-        assert!(Lox::run_file("examples/block/ok_empty.lox").is_ok());
-        assert!(Lox::run_file("examples/block/ok_scope.lox").is_ok());
+        check(&["examples/block/ok_empty.lox", "examples/block/ok_scope.lox"]);
     }
 
     #[test]
     fn check_bool() {
-        assert!(Lox::run_file("examples/bool/ok_equality.lox").is_ok());
-        assert!(Lox::run_file("examples/bool/ok_not.lox").is_ok());
+        check(&["examples/bool/ok_equality.lox", "examples/bool/ok_not.lox"]);
+    }
+
+    #[test]
+    fn check_break() {
+        check(&[
+            "examples/break/err_in_function_in_loop.lox",
+            "examples/break/err_missing_semicolon.lox",
+            "examples/break/err_outside_loop.lox",
+            "examples/break/ok_closure_in_body.lox",
+            "examples/break/ok_for.lox",
+            "examples/break/ok_nested_loop.lox",
+            "examples/break/ok_while.lox",
+        ]);
     }
 
     #[test]
     fn check_call() {
-        assert!(Lox::run_file("examples/call/err_bool.lox").is_err());
-        assert!(Lox::run_file("examples/call/err_nil.lox").is_err());
-        assert!(Lox::run_file("examples/call/err_num.lox").is_err());
-        assert!(Lox::run_file("examples/call/err_object.lox").is_err());
-        assert!(Lox::run_file("examples/call/err_string.lox").is_err());
+        check(&[
+            "examples/call/err_bool.lox",
+            "examples/call/err_native_arity.lox",
+            "examples/call/err_nil.lox",
+            "examples/call/err_num.lox",
+            "examples/call/err_object.lox",
+            "examples/call/err_string.lox",
+            "examples/call/ok_native.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_class() {
+        check(&[
+            "examples/class/err_inherit_self.lox",
+            "examples/class/ok_empty.lox",
+            "examples/class/ok_local_reference_self.lox",
+            "examples/class/ok_reference_self.lox",
+        ]);
     }
 
     #[test]
     fn check_closure() {
-        assert!(Lox::run_file("examples/closure/ok_assign_to_closure.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_assign_to_shadowed_later.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_close_over_function_parameter.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_close_over_later_variable.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_closed_closure_in_function.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_nested_closure.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_open_closure_in_function.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_reference_closure_multiple_times.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_reuse_closure_slot.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_shadow_closure_with_local.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_unused_closure.lox").is_ok());
-        assert!(Lox::run_file("examples/closure/ok_unused_later_closure.lox").is_ok());
+        check(&[
+            "examples/closure/ok_assign_to_closure.lox",
+            "examples/closure/ok_assign_to_shadowed_later.lox",
+            "examples/closure/ok_close_over_function_parameter.lox",
+            "examples/closure/ok_close_over_later_variable.lox",
+            "examples/closure/ok_closed_closure_in_function.lox",
+            "examples/closure/ok_nested_closure.lox",
+            "examples/closure/ok_open_closure_in_function.lox",
+            "examples/closure/ok_reference_closure_multiple_times.lox",
+            "examples/closure/ok_reuse_closure_slot.lox",
+            "examples/closure/ok_shadow_closure_with_local.lox",
+            "examples/closure/ok_unused_closure.lox",
+            "examples/closure/ok_unused_later_closure.lox",
+        ]);
     }
 
     #[test]
     fn check_comments() {
-        assert!(Lox::run_file("examples/comments/ok_line_at_eof.lox").is_ok());
-        assert!(Lox::run_file("examples/comments/ok_only_line_comment.lox").is_ok());
-        assert!(Lox::run_file("examples/comments/ok_only_line_comment_and_line.lox").is_ok());
-        assert!(Lox::run_file("examples/comments/ok_unicode.lox").is_ok());
+        check(&[
+            "examples/comments/err_block_nested_unterminated.lox",
+            "examples/comments/err_block_unterminated.lox",
+            "examples/comments/err_line_after_block.lox",
+            "examples/comments/ok_block.lox",
+            "examples/comments/ok_block_nested.lox",
+            "examples/comments/ok_line_at_eof.lox",
+            "examples/comments/ok_only_line_comment.lox",
+            "examples/comments/ok_only_line_comment_and_line.lox",
+            "examples/comments/ok_unicode.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_constructor() {
+        check(&[
+            "examples/constructor/err_default_arguments.lox",
+            "examples/constructor/err_missing_arguments.lox",
+            "examples/constructor/err_return_value.lox",
+            "examples/constructor/ok_arguments.lox",
+            "examples/constructor/ok_call_init_explicitly.lox",
+            "examples/constructor/ok_default.lox",
+            "examples/constructor/ok_early_return.lox",
+            "examples/constructor/ok_init_not_method.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_continue() {
+        check(&[
+            "examples/continue/err_in_method_in_loop.lox",
+            "examples/continue/err_outside_loop.lox",
+            "examples/continue/ok_for_runs_increment.lox",
+            "examples/continue/ok_nested_block.lox",
+            "examples/continue/ok_while.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_exception() {
+        check(&[
+            "examples/exception/err_catch_without_variable.lox",
+            "examples/exception/err_error_in_catch.lox",
+            "examples/exception/err_exception_property.lox",
+            "examples/exception/err_try_without_catch.lox",
+            "examples/exception/err_uncaught_after_finally.lox",
+            "examples/exception/err_uncaught_throw.lox",
+            "examples/exception/ok_catch_runtime_error.lox",
+            "examples/exception/ok_finally.lox",
+            "examples/exception/ok_finally_overrides.lox",
+            "examples/exception/ok_nested.lox",
+            "examples/exception/ok_throw_and_catch.lox",
+            "examples/exception/ok_throw_through_calls.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_field() {
+        check(&[
+            "examples/field/err_get_on_nil.lox",
+            "examples/field/err_set_on_num.lox",
+            "examples/field/err_undefined.lox",
+            "examples/field/ok_call_function_field.lox",
+            "examples/field/ok_method_binds_this.lox",
+            "examples/field/ok_on_instance.lox",
+        ]);
     }
 
     #[test]
     fn check_for() {
-        assert!(Lox::run_file("examples/for/err_class_in_body.lox").is_err());
-        assert!(Lox::run_file("examples/for/err_fun_in_body.lox").is_err());
-        assert!(Lox::run_file("examples/for/err_statement_condition.lox").is_err());
-        assert!(Lox::run_file("examples/for/err_statement_increment.lox").is_err());
-        assert!(Lox::run_file("examples/for/err_statement_initializer.lox").is_err());
-        assert!(Lox::run_file("examples/for/err_var_in_body.lox").is_err());
-        assert!(Lox::run_file("examples/for/ok_closure_in_body.lox").is_ok());
-        assert!(Lox::run_file("examples/for/ok_return_closure.lox").is_ok());
-        assert!(Lox::run_file("examples/for/ok_return_inside.lox").is_ok());
-        assert!(Lox::run_file("examples/for/ok_scope.lox").is_ok());
-        assert!(Lox::run_file("examples/for/ok_syntax.lox").is_ok());
+        check(&[
+            "examples/for/err_class_in_body.lox",
+            "examples/for/err_fun_in_body.lox",
+            "examples/for/err_statement_condition.lox",
+            "examples/for/err_statement_increment.lox",
+            "examples/for/err_statement_initializer.lox",
+            "examples/for/err_var_in_body.lox",
+            "examples/for/ok_closure_in_body.lox",
+            "examples/for/ok_return_closure.lox",
+            "examples/for/ok_return_inside.lox",
+            "examples/for/ok_scope.lox",
+            "examples/for/ok_syntax.lox",
+        ]);
     }
 
     #[test]
     fn check_function() {
-        assert!(Lox::run_file("examples/function/err_body_must_be_block.lox").is_err());
-        assert!(Lox::run_file("examples/function/err_extra_arguments.lox").is_err());
-        assert!(Lox::run_file("examples/function/err_missing_arguments.lox").is_err());
-        assert!(Lox::run_file("examples/function/err_missing_comma_in_parameters.lox").is_err());
-        assert!(Lox::run_file("examples/function/err_too_many_arguments.lox").is_err());
-        assert!(Lox::run_file("examples/function/err_too_many_parameters.lox").is_err());
-        assert!(Lox::run_file("examples/function/ok_empty_body.lox").is_ok());
-        assert!(Lox::run_file("examples/function/ok_local_recursion.lox").is_ok());
-        assert!(Lox::run_file("examples/function/ok_mutual_recursion.lox").is_ok());
-        assert!(Lox::run_file("examples/function/ok_nested_call_with_arguments.lox").is_ok());
-        assert!(Lox::run_file("examples/function/ok_parameters.lox").is_ok());
-        assert!(Lox::run_file("examples/function/ok_print.lox").is_ok());
-        assert!(Lox::run_file("examples/function/ok_recursion.lox").is_ok());
+        check(&[
+            "examples/function/err_body_must_be_block.lox",
+            "examples/function/err_extra_arguments.lox",
+            "examples/function/err_missing_argument.lox",
+            "examples/function/err_missing_arguments.lox",
+            "examples/function/err_missing_comma_in_parameters.lox",
+            "examples/function/err_stack_overflow.lox",
+            "examples/function/err_too_many_arguments.lox",
+            "examples/function/err_too_many_parameters.lox",
+            "examples/function/ok_deep_recursion.lox",
+            "examples/function/ok_empty_body.lox",
+            "examples/function/ok_local_recursion.lox",
+            "examples/function/ok_mutual_recursion.lox",
+            "examples/function/ok_nested_call_with_arguments.lox",
+            "examples/function/ok_parameters.lox",
+            "examples/function/ok_print.lox",
+            "examples/function/ok_recursion.lox",
+        ]);
     }
 
     #[test]
     fn check_if() {
-        assert!(Lox::run_file("examples/if/err_class_in_else.lox").is_err());
-        assert!(Lox::run_file("examples/if/err_class_in_then.lox").is_err());
-        assert!(Lox::run_file("examples/if/err_fun_in_else.lox").is_err());
-        assert!(Lox::run_file("examples/if/err_fun_in_then.lox").is_err());
-        assert!(Lox::run_file("examples/if/err_var_in_else.lox").is_err());
-        assert!(Lox::run_file("examples/if/err_var_in_then.lox").is_err());
-        assert!(Lox::run_file("examples/if/ok_dangling_else.lox").is_ok());
-        assert!(Lox::run_file("examples/if/ok_else.lox").is_ok());
-        assert!(Lox::run_file("examples/if/ok_if.lox").is_ok());
-        assert!(Lox::run_file("examples/if/ok_truth.lox").is_ok());
+        check(&[
+            "examples/if/err_class_in_else.lox",
+            "examples/if/err_class_in_then.lox",
+            "examples/if/err_fun_in_else.lox",
+            "examples/if/err_fun_in_then.lox",
+            "examples/if/err_var_in_else.lox",
+            "examples/if/err_var_in_then.lox",
+            "examples/if/ok_dangling_else.lox",
+            "examples/if/ok_else.lox",
+            "examples/if/ok_if.lox",
+            "examples/if/ok_truth.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_import() {
+        check(&[
+            "examples/import/err_cycle.lox",
+            "examples/import/err_invalid_module_name.lox",
+            "examples/import/err_missing_file.lox",
+            "examples/import/err_missing_from.lox",
+            "examples/import/err_module_runtime_error.lox",
+            "examples/import/err_self_import.lox",
+            "examples/import/err_undefined_member.lox",
+            "examples/import/ok_catch_module_throw.lox",
+            "examples/import/ok_import_from.lox",
+            "examples/import/ok_local_scope.lox",
+            "examples/import/ok_module_state.lox",
+            "examples/import/ok_namespace.lox",
+            "examples/import/ok_nested.lox",
+            "examples/import/ok_run_once.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_inheritance() {
+        check(&[
+            "examples/inheritance/err_inherit_from_function.lox",
+            "examples/inheritance/err_inherit_from_nil.lox",
+            "examples/inheritance/err_inherit_from_number.lox",
+            "examples/inheritance/err_parenthesized_superclass.lox",
+            "examples/inheritance/ok_constructor.lox",
+            "examples/inheritance/ok_inherit_methods.lox",
+            "examples/inheritance/ok_inherited_method.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_lambda() {
+        check(&[
+            "examples/lambda/err_missing_body.lox",
+            "examples/lambda/err_missing_paren.lox",
+            "examples/lambda/err_runtime_error.lox",
+            "examples/lambda/ok_call.lox",
+            "examples/lambda/ok_callback.lox",
+            "examples/lambda/ok_closure.lox",
+            "examples/lambda/ok_expression_statement.lox",
+            "examples/lambda/ok_in_method.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_list() {
+        check(&[
+            "examples/list/err_index_non_list.lox",
+            "examples/list/err_index_out_of_bounds.lox",
+            "examples/list/err_missing_bracket.lox",
+            "examples/list/err_missing_index_bracket.lox",
+            "examples/list/err_negative_index.lox",
+            "examples/list/err_non_integer_index.lox",
+            "examples/list/err_pop_empty.lox",
+            "examples/list/err_slice_out_of_range.lox",
+            "examples/list/ok_index.lox",
+            "examples/list/ok_literal.lox",
+            "examples/list/ok_natives.lox",
+            "examples/list/ok_print_self.lox",
+            "examples/list/ok_reference.lox",
+        ]);
     }
 
     #[test]
     fn check_logical_operator() {
-        assert!(Lox::run_file("examples/logical_operator/ok_and.lox").is_ok());
-        assert!(Lox::run_file("examples/logical_operator/ok_and_truth.lox").is_ok());
-        assert!(Lox::run_file("examples/logical_operator/ok_or.lox").is_ok());
-        assert!(Lox::run_file("examples/logical_operator/ok_or_truth.lox").is_ok());
+        check(&[
+            "examples/logical_operator/ok_and.lox",
+            "examples/logical_operator/ok_and_truth.lox",
+            "examples/logical_operator/ok_or.lox",
+            "examples/logical_operator/ok_or_truth.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_map() {
+        check(&[
+            "examples/map/err_list_key.lox",
+            "examples/map/err_literal_key.lox",
+            "examples/map/err_missing_brace.lox",
+            "examples/map/err_missing_colon.lox",
+            "examples/map/err_nan_key.lox",
+            "examples/map/err_undefined_key.lox",
+            "examples/map/ok_index.lox",
+            "examples/map/ok_literal.lox",
+            "examples/map/ok_natives.lox",
+            "examples/map/ok_number_keys.lox",
+            "examples/map/ok_print_self.lox",
+            "examples/map/ok_reference.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_method() {
+        check(&[
+            "examples/method/err_extra_arguments.lox",
+            "examples/method/err_refer_to_name.lox",
+            "examples/method/ok_empty_block.lox",
+            "examples/method/ok_print_bound_method.lox",
+        ]);
     }
 
     #[test]
     fn check_nil() {
-        assert!(Lox::run_file("examples/nil/ok_literal.lox").is_ok());
+        check(&["examples/nil/ok_literal.lox"]);
     }
 
     #[test]
     fn check_number() {
-        assert!(Lox::run_file("examples/number/err_decimal_point_at_eof.lox").is_err());
-        assert!(Lox::run_file("examples/number/err_leading_dot.lox").is_err());
-        assert!(Lox::run_file("examples/number/err_trailing_dot.lox").is_err());
-        assert!(Lox::run_file("examples/number/ok_literals.lox").is_ok());
-        assert!(Lox::run_file("examples/number/ok_nan_equality.lox").is_ok());
+        check(&[
+            "examples/number/err_decimal_point_at_eof.lox",
+            "examples/number/err_leading_dot.lox",
+            "examples/number/err_trailing_dot.lox",
+            "examples/number/ok_literals.lox",
+            "examples/number/ok_nan_equality.lox",
+        ]);
     }
 
     #[test]
     fn check_operator() {
-        assert!(Lox::run_file("examples/operator/err_add_bool_nil.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_add_bool_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_add_bool_string.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_add_nil_nil.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_add_num_nil.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_add_string_nil.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_divide_nonnum_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_divide_num_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_greater_nonnum_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_greater_num_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_greater_or_equal_nonnum_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_greater_or_equal_num_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_less_nonnum_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_less_num_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_less_or_equal_nonnum_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_less_or_equal_num_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_multiply_nonnum_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_multiply_num_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_negate_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_subtract_nonnum_num.lox").is_err());
-        assert!(Lox::run_file("examples/operator/err_subtract_num_nonnum.lox").is_err());
-        assert!(Lox::run_file("examples/operator/ok_add.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_comparison.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_divide.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_equals.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_multiply.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_negate.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_not.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_not_equals.lox").is_ok());
-        assert!(Lox::run_file("examples/operator/ok_subtract.lox").is_ok());
+        check(&[
+            "examples/operator/err_add_bool_nil.lox",
+            "examples/operator/err_add_bool_num.lox",
+            "examples/operator/err_add_bool_string.lox",
+            "examples/operator/err_add_nil_nil.lox",
+            "examples/operator/err_add_num_nil.lox",
+            "examples/operator/err_add_string_nil.lox",
+            "examples/operator/err_divide_nonnum_num.lox",
+            "examples/operator/err_divide_num_nonnum.lox",
+            "examples/operator/err_greater_nonnum_num.lox",
+            "examples/operator/err_greater_num_nonnum.lox",
+            "examples/operator/err_greater_or_equal_nonnum_num.lox",
+            "examples/operator/err_greater_or_equal_num_nonnum.lox",
+            "examples/operator/err_less_nonnum_num.lox",
+            "examples/operator/err_less_num_nonnum.lox",
+            "examples/operator/err_less_or_equal_nonnum_num.lox",
+            "examples/operator/err_less_or_equal_num_nonnum.lox",
+            "examples/operator/err_multiply_nonnum_num.lox",
+            "examples/operator/err_multiply_num_nonnum.lox",
+            "examples/operator/err_negate_nonnum.lox",
+            "examples/operator/err_subtract_nonnum_num.lox",
+            "examples/operator/err_subtract_num_nonnum.lox",
+            "examples/operator/ok_add.lox",
+            "examples/operator/ok_comparison.lox",
+            "examples/operator/ok_divide.lox",
+            "examples/operator/ok_equals.lox",
+            "examples/operator/ok_multiply.lox",
+            "examples/operator/ok_negate.lox",
+            "examples/operator/ok_not.lox",
+            "examples/operator/ok_not_equals.lox",
+            "examples/operator/ok_subtract.lox",
+        ]);
     }
 
     #[test]
     fn check_others() {
-        assert!(Lox::run_file("examples/others/err_unexpected_character.lox").is_err());
-        assert!(Lox::run_file("examples/others/ok_empty_file.lox").is_ok());
-        assert!(Lox::run_file("examples/others/ok_fibonacci.lox").is_ok());
-        assert!(Lox::run_file("examples/others/ok_precedence.lox").is_ok());
+        check(&[
+            "examples/others/err_multiple_errors.lox",
+            "examples/others/err_recover_at_keywords.lox",
+            "examples/others/err_traceback.lox",
+            "examples/others/err_unexpected_character.lox",
+            "examples/others/ok_empty_file.lox",
+            "examples/others/ok_fibonacci.lox",
+            "examples/others/ok_precedence.lox",
+        ]);
     }
 
     #[test]
     fn check_print() {
-        assert!(Lox::run_file("examples/print/err_missing_argument.lox").is_err());
+        check(&["examples/print/err_missing_argument.lox"]);
     }
 
     #[test]
     fn check_return() {
-        assert!(Lox::run_file("examples/return/err_at_top_level.lox").is_err());
-        assert!(Lox::run_file("examples/return/ok_after_else.lox").is_ok());
-        assert!(Lox::run_file("examples/return/ok_after_if.lox").is_ok());
-        assert!(Lox::run_file("examples/return/ok_after_while.lox").is_ok());
-        assert!(Lox::run_file("examples/return/ok_in_function.lox").is_ok());
-        assert!(Lox::run_file("examples/return/ok_return_nil_if_no_value.lox").is_ok());
+        check(&[
+            "examples/return/err_at_top_level.lox",
+            "examples/return/ok_after_else.lox",
+            "examples/return/ok_after_if.lox",
+            "examples/return/ok_after_while.lox",
+            "examples/return/ok_in_function.lox",
+            "examples/return/ok_return_nil_if_no_value.lox",
+        ]);
     }
 
     #[test]
     fn check_string() {
-        assert!(Lox::run_file("examples/string/err_error_after_multiline.lox").is_err());
-        assert!(Lox::run_file("examples/string/err_unterminated.lox").is_err());
-        assert!(Lox::run_file("examples/string/ok_literals.lox").is_ok());
-        assert!(Lox::run_file("examples/string/ok_multiline.lox").is_ok());
+        check(&[
+            "examples/string/err_empty_interpolation.lox",
+            "examples/string/err_error_after_multiline.lox",
+            "examples/string/err_invalid_escape.lox",
+            "examples/string/err_invalid_unicode_escape.lox",
+            "examples/string/err_unclosed_interpolation.lox",
+            "examples/string/err_unterminated.lox",
+            "examples/string/err_unterminated_multiline.lox",
+            "examples/string/ok_escapes.lox",
+            "examples/string/ok_interpolation.lox",
+            "examples/string/ok_literals.lox",
+            "examples/string/ok_multiline.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_super() {
+        check(&[
+            "examples/super/err_no_superclass_call.lox",
+            "examples/super/err_no_superclass_method.lox",
+            "examples/super/err_parenthesized.lox",
+            "examples/super/err_super_at_top_level.lox",
+            "examples/super/err_super_without_name.lox",
+            "examples/super/ok_bound_method.lox",
+            "examples/super/ok_call_same_method.lox",
+            "examples/super/ok_closure.lox",
+            "examples/super/ok_constructor.lox",
+            "examples/super/ok_indirectly_inherited.lox",
+        ]);
+    }
+
+    #[test]
+    fn check_this() {
+        check(&[
+            "examples/this/err_this_at_top_level.lox",
+            "examples/this/err_this_in_top_level_function.lox",
+            "examples/this/ok_closure.lox",
+            "examples/this/ok_nested_class.lox",
+        ]);
     }
 
     #[test]
     fn check_variable() {
-        assert!(Lox::run_file("examples/variable/err_undefined_global.lox").is_err());
-        assert!(Lox::run_file("examples/variable/err_undefined_local.lox").is_err());
-        assert!(Lox::run_file("examples/variable/err_use_false_as_var.lox").is_err());
-        assert!(Lox::run_file("examples/variable/err_use_nil_as_var.lox").is_err());
-        assert!(Lox::run_file("examples/variable/err_use_this_as_var.lox").is_err());
-        assert!(Lox::run_file("examples/variable/ok_early_bound.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_in_middle_of_block.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_in_nested_block.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_redeclare_global.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_redefine_global.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_scope_reuse_in_different_blocks.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_shadow_and_local.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_shadow_global.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_shadow_local.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_uninitialized.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_unreached_undefined.lox").is_ok());
-        assert!(Lox::run_file("examples/variable/ok_use_global_in_initializer.lox").is_ok());
+        check(&[
+            "examples/variable/err_collide_with_parameter.lox",
+            "examples/variable/err_duplicate_local.lox",
+            "examples/variable/err_duplicate_parameter.lox",
+            "examples/variable/err_undefined_global.lox",
+            "examples/variable/err_undefined_local.lox",
+            "examples/variable/err_use_false_as_var.lox",
+            "examples/variable/err_use_local_in_initializer.lox",
+            "examples/variable/err_use_nil_as_var.lox",
+            "examples/variable/err_use_this_as_var.lox",
+            "examples/variable/ok_early_bound.lox",
+            "examples/variable/ok_in_middle_of_block.lox",
+            "examples/variable/ok_in_nested_block.lox",
+            "examples/variable/ok_redeclare_global.lox",
+            "examples/variable/ok_redefine_global.lox",
+            "examples/variable/ok_scope_reuse_in_different_blocks.lox",
+            "examples/variable/ok_shadow_and_local.lox",
+            "examples/variable/ok_shadow_global.lox",
+            "examples/variable/ok_shadow_local.lox",
+            "examples/variable/ok_uninitialized.lox",
+            "examples/variable/ok_unreached_undefined.lox",
+            "examples/variable/ok_use_global_in_initializer.lox",
+        ]);
     }
 
     #[test]
     fn check_while() {
-        assert!(Lox::run_file("examples/while/err_class_in_body.lox").is_err());
-        assert!(Lox::run_file("examples/while/err_fun_in_body.lox").is_err());
-        assert!(Lox::run_file("examples/while/err_var_in_body.lox").is_err());
-        assert!(Lox::run_file("examples/while/ok_closure_in_body.lox").is_ok());
-        assert!(Lox::run_file("examples/while/ok_return_closure.lox").is_ok());
-        assert!(Lox::run_file("examples/while/ok_return_inside.lox").is_ok());
-        assert!(Lox::run_file("examples/while/ok_syntax.lox").is_ok());
+        check(&[
+            "examples/while/err_class_in_body.lox",
+            "examples/while/err_fun_in_body.lox",
+            "examples/while/err_var_in_body.lox",
+            "examples/while/ok_closure_in_body.lox",
+            "examples/while/ok_return_closure.lox",
+            "examples/while/ok_return_inside.lox",
+            "examples/while/ok_syntax.lox",
+        ]);
     }
 }
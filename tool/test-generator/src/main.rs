@@ -4,15 +4,19 @@ use std::io::{self};
 use std::path::Path;
 use std::path::PathBuf;
 
+// Scripts whose expectations the interpreter does not meet yet.
+// They are generated into an ignored test, run with `cargo test -- --ignored`.
+const PENDING: &[&str] = &[];
+
 fn main() -> Result<()> {
     let tests_path = Path::new("../../tests/main.rs");
     let examples_path = Path::new("../../examples");
 
     let mut src: Vec<String> = [
-        "mod tests {",
-        "\textern crate rslox;",
+        "mod common;",
         "",
-        "\tuse rslox::lox::Lox;",
+        "mod tests {",
+        "\tuse crate::common::check;",
     ]
     .iter()
     .map(|s| s.to_string())
@@ -30,6 +34,10 @@ fn main() -> Result<()> {
         }
     }
 
+    if !PENDING.is_empty() {
+        src.extend_from_slice(&pending());
+    }
+
     src.push("}".to_string());
 
     fs::write(tests_path, src.join("\n"))?;
@@ -58,31 +66,45 @@ fn path_to_string(path: &Path) -> Result<String> {
 fn method(dir: &str, entries: &[PathBuf]) -> Result<Vec<String>> {
     let mut method: Vec<String> = ["", "\t#[test]"].iter().map(|s| s.to_string()).collect();
     method.push(format!("\tfn check_{}() {{", dir));
-    method.extend_from_slice(&asserts(entries)?);
+    method.push("\t\tcheck(&[".to_string());
+    method.extend_from_slice(&scripts(entries)?);
+    method.push("\t\t]);".to_string());
     method.push("\t}".to_string());
 
     Ok(method)
 }
 
-fn asserts(files: &[PathBuf]) -> Result<Vec<String>> {
+fn pending() -> Vec<String> {
+    let mut method: Vec<String> = ["", "\t#[test]", "\t#[ignore]", "\tfn check_pending() {"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    method.push("\t\tcheck(&[".to_string());
+    for path in PENDING {
+        method.push(format!("\t\t\t\"{}\",", path));
+    }
+    method.push("\t\t]);".to_string());
+    method.push("\t}".to_string());
+
+    method
+}
+
+// Scripts are run by `tests/common` and checked against their `// expect` comments.
+fn scripts(files: &[PathBuf]) -> Result<Vec<String>> {
     let mut scripts = Vec::new();
     for f in files {
-        let file_name = path_to_string(f)?;
-        let suffix = if file_name.starts_with("ok_") {
-            ".is_ok()"
-        } else if file_name.starts_with("err_") {
-            ".is_err()"
-        } else {
+        if f.extension().is_none_or(|e| e != "lox") {
             continue;
-        };
+        }
+
+        let path = f.strip_prefix("../../")?.to_string_lossy();
+        if PENDING.contains(&path.as_ref()) {
+            continue;
+        }
 
-        let assert = format!(
-            "\t\tassert!(Lox::run_file(\"{}\"){});",
-            f.strip_prefix("../../")?.to_string_lossy(),
-            suffix
-        );
+        let script = format!("\t\t\t\"{}\",", path);
 
-        scripts.push(assert);
+        scripts.push(script);
     }
 
     Ok(scripts)